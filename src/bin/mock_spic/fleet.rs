@@ -0,0 +1,260 @@
+use std::f64::consts::PI;
+
+use chrono::{DateTime, Utc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+const FIRST_UNIT_ID: i32 = 80000;
+
+const BRANDS: [(&str, &str); 4] = [
+    ("KAMAZ", "65115"),
+    ("GAZ", "GAZelle Next"),
+    ("MAZ", "5440"),
+    ("Volvo", "FH16"),
+];
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct FleetConfig {
+    pub units: usize,
+    /// Same seed, same fleet
+    pub seed: u64,
+    pub center_latitude: f64,
+    pub center_longitude: f64,
+    /// Units drive on circles around the center up to this distance
+    pub radius_km: f64,
+    pub max_speed_kmh: f64,
+    /// Share of units that stand still
+    pub parked_share: f64,
+    /// Share of units that never send online data
+    pub offline_share: f64,
+    pub units_per_group: usize,
+}
+
+impl Default for FleetConfig {
+    fn default() -> Self {
+        FleetConfig {
+            units: 50,
+            seed: 1,
+            center_latitude: 56.8389,
+            center_longitude: 60.6057,
+            radius_km: 20.0,
+            max_speed_kmh: 90.0,
+            parked_share: 0.2,
+            offline_share: 0.05,
+            units_per_group: 10,
+        }
+    }
+}
+
+/// Fake vehicle driving a circle around the fleet center at constant speed.
+#[derive(Debug)]
+pub struct FakeUnit {
+    pub id: i32,
+    pub name: String,
+    brand: &'static str,
+    model: &'static str,
+    state_number: String,
+    vin: String,
+    year: i32,
+    /// Circle around the center, km
+    radius_km: f64,
+    /// Angle on the circle at `started`, radians
+    phase: f64,
+    speed_kmh: f64,
+    /// Counter-clockwise when false
+    clockwise: bool,
+    pub online: bool,
+}
+
+/// Position of a unit at a point in time.
+#[derive(Debug, Clone, Copy)]
+pub struct FakePosition {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub speed_kmh: f64,
+    pub course: i32,
+    pub altitude_m: i32,
+    pub satellites: i8,
+}
+
+#[derive(Debug)]
+pub struct Fleet {
+    config: FleetConfig,
+    started: DateTime<Utc>,
+    pub units: Vec<FakeUnit>,
+}
+
+impl Fleet {
+    pub fn new(config: FleetConfig, started: DateTime<Utc>) -> Self {
+        let mut rng = StdRng::seed_from_u64(config.seed);
+
+        let units = (0..config.units)
+            .map(|i| {
+                let (brand, model) = BRANDS[i % BRANDS.len()];
+                let parked = rng.gen_bool(config.parked_share.clamp(0.0, 1.0));
+
+                FakeUnit {
+                    id: FIRST_UNIT_ID + i as i32,
+                    name: format!("{} {:03}", brand, i + 1),
+                    brand,
+                    model,
+                    state_number: format!("A{:03}MC96", i + 1),
+                    vin: format!("XTC{:014}", rng.gen_range(0..100_000_000_000_000u64)),
+                    year: rng.gen_range(2010..=2024),
+                    radius_km: rng.gen_range(0.5..config.radius_km.max(1.0)),
+                    phase: rng.gen_range(0.0..2.0 * PI),
+                    speed_kmh: if parked { 0.0 } else { rng.gen_range(20.0..config.max_speed_kmh.max(21.0)) },
+                    clockwise: rng.gen_bool(0.5),
+                    online: !rng.gen_bool(config.offline_share.clamp(0.0, 1.0)),
+                }
+            })
+            .collect();
+
+        Fleet { config, started, units }
+    }
+
+    pub fn unit(&self, id: i32) -> Option<&FakeUnit> {
+        self.units.iter().find(|unit| unit.id == id)
+    }
+
+    pub fn position(&self, unit: &FakeUnit, at: DateTime<Utc>) -> FakePosition {
+        let hours = (at - self.started).num_milliseconds() as f64 / 3_600_000.0;
+        let direction = if unit.clockwise { -1.0 } else { 1.0 };
+        let angle = unit.phase + direction * unit.speed_kmh * hours / unit.radius_km;
+
+        let north_km = unit.radius_km * angle.sin();
+        let east_km = unit.radius_km * angle.cos();
+        let latitude = self.config.center_latitude + (north_km / EARTH_RADIUS_KM).to_degrees();
+        let longitude = self.config.center_longitude
+            + (east_km / (EARTH_RADIUS_KM * self.config.center_latitude.to_radians().cos())).to_degrees();
+
+        // Tangent of the circle, as a compass bearing
+        let heading = (angle + direction * PI / 2.0).rem_euclid(2.0 * PI);
+        let course = (90.0 - heading.to_degrees()).rem_euclid(360.0);
+
+        FakePosition {
+            latitude,
+            longitude,
+            speed_kmh: unit.speed_kmh,
+            course: course.round() as i32 % 360,
+            altitude_m: 250 + (30.0 * angle.sin()) as i32,
+            satellites: if unit.speed_kmh > 0.0 { 12 } else { 9 },
+        }
+    }
+
+    pub fn groups(&self) -> Vec<Value> {
+        self.units
+            .chunks(self.config.units_per_group.max(1))
+            .enumerate()
+            .map(|(i, units)| {
+                json!({
+                    "UnitGroupId": i + 1,
+                    "Name": format!("Column {}", i + 1),
+                    "Description": "",
+                    "CompanyId": 1,
+                    "UnitIds": units.iter().map(|unit| unit.id).collect::<Vec<_>>(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// `/Date(ms+0000)/` as SPIC writes dates.
+pub fn ms_date(date: DateTime<Utc>) -> String {
+    format!("/Date({}+0000)/", date.timestamp_millis())
+}
+
+impl FakeUnit {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "Brand": self.brand,
+            "Color": "white",
+            "CompanyId": 1,
+            "Description": "",
+            "GarageNumber": format!("{}", self.id - FIRST_UNIT_ID + 1),
+            "Model": self.model,
+            "Name": self.name,
+            "OlsonId": "Asia/Yekaterinburg",
+            "Owner": "Mock fleet",
+            "Power": "",
+            "Registration": "",
+            "StateNumber": self.state_number,
+            "UnitId": self.id,
+            "UnitTypeId": 1,
+            "VinNumber": self.vin,
+            "Year": self.year.to_string(),
+        })
+    }
+}
+
+impl FakePosition {
+    pub fn navigation_json(&self) -> Value {
+        json!({
+            "AltitudeMeters": self.altitude_m,
+            "Angle": self.course,
+            "HardwareValidation": null,
+            "Location": {
+                "Latitude": self.latitude,
+                "Longitude": self.longitude,
+            },
+            "NavigationSystemType": "GlonassGps",
+            "SatellitesCount": self.satellites,
+            "Speed": self.speed_kmh.round() as i32,
+        })
+    }
+}
+
+#[cfg(test)]
+mod fleet_tests {
+    use super::*;
+
+    fn fleet() -> Fleet {
+        Fleet::new(
+            FleetConfig {
+                parked_share: 0.0,
+                ..FleetConfig::default()
+            },
+            DateTime::from_timestamp(1735972469, 0).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_same_seed_same_fleet() {
+        let (first, second) = (fleet(), fleet());
+
+        assert_eq!(first.units.len(), 50);
+        assert_eq!(first.units[3].vin, second.units[3].vin);
+        assert_eq!(first.units[3].radius_km, second.units[3].radius_km);
+    }
+
+    #[test]
+    fn test_units_move_at_their_speed() {
+        let fleet = fleet();
+        let unit = &fleet.units[0];
+        let start = fleet.started;
+
+        let before = fleet.position(unit, start);
+        let after = fleet.position(unit, start + chrono::Duration::seconds(60));
+
+        // Straight line distance of one minute on the circle, slightly less than the arc
+        let north_km = (after.latitude - before.latitude).to_radians() * EARTH_RADIUS_KM;
+        let east_km = (after.longitude - before.longitude).to_radians()
+            * EARTH_RADIUS_KM
+            * fleet.config.center_latitude.to_radians().cos();
+        let distance_km = (north_km.powi(2) + east_km.powi(2)).sqrt();
+        let arc_km = unit.speed_kmh / 60.0;
+
+        assert!(distance_km > arc_km * 0.9 && distance_km <= arc_km * 1.01);
+    }
+
+    #[test]
+    fn test_ms_date_round_trip() {
+        let date = DateTime::from_timestamp_millis(1735972469975).unwrap();
+
+        assert_eq!(sc_rdl_rust::ms_date::parse_ms_date(&ms_date(date)), Some(date.fixed_offset()));
+    }
+}
@@ -0,0 +1,423 @@
+//! Local stand-in for the SPIC services used by `SpicClient`, backed by a
+//! fake fleet that keeps moving while the server runs. Point `base_url` of
+//! `[spic]` at it, e.g. `http://127.0.0.1:8090/spic`.
+//!
+//! `cargo run --features mock --bin mock_spic [config file]`, `config/mock_spic.toml` by default.
+
+mod fleet;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::Utc;
+use config::{Config, File};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::fleet::{ms_date, Fleet, FleetConfig};
+use sc_rdl_rust::ms_date::parse_ms_date;
+
+const DEFAULT_CONFIG: &str = "config/mock_spic";
+
+const BAD_REQUEST: i32 = 200;
+const TERMINAL_NOT_FOUND: i32 = 203;
+const SUBSCRIPTION_NOT_FOUND: i32 = 204;
+const ONLINE_DATA_NOT_FOUND: i32 = 205;
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+struct MockConfig {
+    address: String,
+    port: u16,
+    /// Prefix of every endpoint, the `/spic` of `base_url`
+    base_path: String,
+    login: String,
+    password: String,
+    session_ttl_s: i64,
+    /// Time until a subscription has data for all units, half of the units
+    /// are ready after half of it
+    subscription_delay_ms: u64,
+    /// Subscriptions not polled for this long are forgotten
+    subscription_ttl_s: u64,
+    /// Chance that a subscribe request is answered with `Busy`
+    busy_probability: f64,
+    /// Time between the stored messages of a unit
+    history_interval_s: i64,
+    fleet: FleetConfig,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        MockConfig {
+            address: "127.0.0.1".to_string(),
+            port: 8090,
+            base_path: "/spic".to_string(),
+            login: "mock".to_string(),
+            password: "mock".to_string(),
+            session_ttl_s: 3600,
+            subscription_delay_ms: 2000,
+            subscription_ttl_s: 300,
+            busy_probability: 0.1,
+            history_interval_s: 30,
+            fleet: FleetConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Subscription {
+    unit_ids: Vec<i32>,
+    created: Instant,
+    last_polled: Instant,
+}
+
+struct MockState {
+    config: MockConfig,
+    fleet: Fleet,
+    sessions: Mutex<HashMap<String, chrono::DateTime<Utc>>>,
+    subscriptions: Mutex<HashMap<String, Subscription>>,
+    rng: Mutex<StdRng>,
+}
+
+type SharedState = Arc<MockState>;
+
+impl MockState {
+    fn new_id(&self) -> String {
+        let value: u128 = self.rng.lock().unwrap().gen();
+        let hex = format!("{:032x}", value);
+
+        format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+    }
+
+    /// `401` unless the `ScoutAuthorization` header holds a live session.
+    fn authorize(&self, headers: &HeaderMap) -> Result<(), StatusCode> {
+        let session = headers
+            .get("ScoutAuthorization")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+
+        match self.sessions.lock().unwrap().get(session) {
+            Some(expires) if *expires > Utc::now() => Ok(()),
+            _ => Err(StatusCode::UNAUTHORIZED),
+        }
+    }
+}
+
+fn od_state(status: &str, error_codes: &[i32]) -> Value {
+    json!({
+        "ErrorCodes": error_codes,
+        "Status": { "Value": status },
+    })
+}
+
+async fn login(State(state): State<SharedState>, body: String) -> Response {
+    let request: Value = serde_json::from_str(&body).unwrap_or_default();
+    let authorized = request["Login"] == state.config.login.as_str()
+        && request["Password"] == state.config.password.as_str();
+
+    let session_id = state.new_id();
+    let expires = Utc::now() + chrono::Duration::seconds(state.config.session_ttl_s);
+
+    if authorized {
+        state.sessions.lock().unwrap().insert(session_id.clone(), expires);
+        println!("Login of {}, session expires at {}", state.config.login, expires);
+    } else {
+        println!("Rejected login of {}", request["Login"]);
+    }
+
+    Json(json!({
+        "ExpireDate": ms_date(expires),
+        "IsAuthenticated": authorized,
+        "IsAuthorized": authorized,
+        "SessionId": if authorized { session_id } else { String::new() },
+        "UserId": 1,
+        "UserName": state.config.login,
+    }))
+    .into_response()
+}
+
+async fn logout(State(state): State<SharedState>, headers: HeaderMap) -> Response {
+    if let Some(session) = headers.get("ScoutAuthorization").and_then(|value| value.to_str().ok()) {
+        state.sessions.lock().unwrap().remove(session);
+    }
+
+    Json(true).into_response()
+}
+
+async fn units_count(State(state): State<SharedState>, headers: HeaderMap) -> Response {
+    if let Err(status) = state.authorize(&headers) {
+        return status.into_response();
+    }
+
+    state.fleet.units.len().to_string().into_response()
+}
+
+async fn units(State(state): State<SharedState>, headers: HeaderMap) -> Response {
+    if let Err(status) = state.authorize(&headers) {
+        return status.into_response();
+    }
+
+    let units = state.fleet.units.iter().map(|unit| unit.to_json()).collect::<Vec<_>>();
+    Json(json!({ "Units": units })).into_response()
+}
+
+async fn unit_groups(State(state): State<SharedState>, headers: HeaderMap) -> Response {
+    if let Err(status) = state.authorize(&headers) {
+        return status.into_response();
+    }
+
+    Json(json!({ "UnitGroups": state.fleet.groups() })).into_response()
+}
+
+/// Messages every `history_interval_s` between `From` and `To`, paged by `Skip` and `Take`.
+/// The paging mirrors the client's assumption, it is not taken from a recorded SPIC response.
+async fn messages(State(state): State<SharedState>, headers: HeaderMap, body: String) -> Response {
+    if let Err(status) = state.authorize(&headers) {
+        return status.into_response();
+    }
+
+    let request: Value = serde_json::from_str(&body).unwrap_or_default();
+    let unit = request["UnitId"]
+        .as_i64()
+        .and_then(|id| state.fleet.unit(id as i32));
+    let from = request["From"].as_str().and_then(parse_ms_date).map(|date| date.to_utc());
+    let to = request["To"].as_str().and_then(parse_ms_date).map(|date| date.to_utc());
+
+    let (Some(unit), Some(from), Some(to)) = (unit, from, to) else {
+        return (StatusCode::BAD_REQUEST, "UnitId, From and To are required").into_response();
+    };
+
+    let skip = request["Skip"].as_u64().unwrap_or(0) as usize;
+    let take = request["Take"].as_u64().unwrap_or(1000) as usize;
+    let interval = chrono::Duration::seconds(state.config.history_interval_s.max(1));
+    let to = to.min(Utc::now());
+
+    let messages = std::iter::successors(Some(from), |time| Some(*time + interval))
+        .take_while(|time| *time < to)
+        .skip(skip)
+        .take(take)
+        .map(|time| {
+            json!({
+                "MessageTime": ms_date(time),
+                "Navigation": state.fleet.position(unit, time).navigation_json(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Json(json!({ "Messages": messages })).into_response()
+}
+
+async fn subscribe(State(state): State<SharedState>, headers: HeaderMap, body: String) -> Response {
+    if let Err(status) = state.authorize(&headers) {
+        return status.into_response();
+    }
+
+    let respond = |session_id: &str, status: &str, codes: &[i32]| {
+        Json(json!({
+            "SessionId": { "Id": session_id },
+            "State": od_state(status, codes),
+        }))
+        .into_response()
+    };
+
+    let request: Value = serde_json::from_str(&body).unwrap_or_default();
+    let Some(requested) = request["UnitIds"].as_array() else {
+        return respond("", "Error", &[BAD_REQUEST]);
+    };
+
+    let busy = state.rng.lock().unwrap().gen_bool(state.config.busy_probability.clamp(0.0, 1.0));
+    if busy {
+        println!("Subscribe answered with Busy");
+        return respond("", "Busy", &[]);
+    }
+
+    let unit_ids = requested
+        .iter()
+        .filter_map(|id| id.as_i64())
+        .map(|id| id as i32)
+        .collect::<Vec<_>>();
+
+    if !unit_ids.iter().any(|id| state.fleet.unit(*id).is_some()) {
+        return respond("", "Error", &[TERMINAL_NOT_FOUND]);
+    }
+
+    let subscription_id = state.new_id();
+    let now = Instant::now();
+    println!("Subscription {} for {} units", subscription_id, unit_ids.len());
+
+    state.subscriptions.lock().unwrap().insert(
+        subscription_id.clone(),
+        Subscription {
+            unit_ids,
+            created: now,
+            last_polled: now,
+        },
+    );
+
+    respond(&subscription_id, "Ok", &[])
+}
+
+/// `Busy` until half of the subscription delay passed, `PartialOk` with the
+/// first half of the units until all of it passed, `Ok` after that. Units
+/// that are offline or unknown keep the answer at `PartialOk`; they are
+/// listed in `Targets` after the units with data, one error code each.
+async fn online_data(State(state): State<SharedState>, headers: HeaderMap, body: String) -> Response {
+    if let Err(status) = state.authorize(&headers) {
+        return status.into_response();
+    }
+
+    let request: Value = serde_json::from_str(&body).unwrap_or_default();
+    let subscription_id = request["Id"].as_str().unwrap_or_default();
+
+    let respond = |targets: &[i32], data: Option<Vec<Value>>, status: &str, codes: &[i32]| {
+        Json(json!({
+            "OnlineDataCollection": {
+                "DataCollection": data,
+                "Targets": targets,
+            },
+            "State": od_state(status, codes),
+        }))
+        .into_response()
+    };
+
+    let ttl = Duration::from_secs(state.config.subscription_ttl_s);
+    let (unit_ids, elapsed) = {
+        let mut subscriptions = state.subscriptions.lock().unwrap();
+        subscriptions.retain(|_, subscription| subscription.last_polled.elapsed() < ttl);
+
+        match subscriptions.get_mut(subscription_id) {
+            Some(subscription) => {
+                subscription.last_polled = Instant::now();
+                (subscription.unit_ids.clone(), subscription.created.elapsed())
+            }
+            None => return respond(&[], None, "Error", &[SUBSCRIPTION_NOT_FOUND]),
+        }
+    };
+
+    let delay = Duration::from_millis(state.config.subscription_delay_ms);
+    if elapsed < delay / 2 {
+        return respond(&unit_ids, None, "Busy", &[]);
+    }
+
+    let ready = if elapsed < delay { unit_ids.len().div_ceil(2) } else { unit_ids.len() };
+    let now = Utc::now();
+    let mut codes = Vec::new();
+    let mut failed = Vec::new();
+    let mut data = Vec::new();
+
+    for unit_id in &unit_ids[..ready] {
+        match state.fleet.unit(*unit_id) {
+            Some(unit) if unit.online => {
+                let position = state.fleet.position(unit, now);
+                let moving = position.speed_kmh > 0.0;
+
+                data.push(json!({
+                    "UnitId": unit.id,
+                    "Address": "",
+                    "ConnectionDateTime": ms_date(now - chrono::Duration::hours(2)),
+                    "DeviceId": {
+                        "Protocol": { "Name": "Mock", "Version": "1.0" },
+                        "SerialId": format!("MOCK{}", unit.id),
+                    },
+                    "IsNavigationValid": true,
+                    "LastMessageTime": ms_date(now),
+                    "Navigation": position.navigation_json(),
+                    "NavigationTime": ms_date(now),
+                    "TotalMessages": (now.timestamp() / 30) % 100_000,
+                    "Sensors": [
+                        { "Name": "Ignition", "Value": moving },
+                        { "Name": "Voltage", "Value": if moving { 27.9 } else { 24.6 } },
+                    ],
+                }));
+            }
+            Some(_) => {
+                failed.push(*unit_id);
+                codes.push(ONLINE_DATA_NOT_FOUND);
+            }
+            None => {
+                failed.push(*unit_id);
+                codes.push(TERMINAL_NOT_FOUND);
+            }
+        }
+    }
+
+    let status = if ready == unit_ids.len() && codes.is_empty() { "Ok" } else { "PartialOk" };
+    let targets = unit_ids[..ready]
+        .iter()
+        .filter(|id| !failed.contains(id))
+        .chain(&failed)
+        .copied()
+        .collect::<Vec<_>>();
+
+    respond(&targets, Some(data), status, &codes)
+}
+
+fn load_config() -> Result<MockConfig, config::ConfigError> {
+    let file_name = std::env::args().nth(1).unwrap_or_else(|| DEFAULT_CONFIG.to_string());
+
+    Config::builder()
+        .add_source(File::with_name(&file_name).required(false))
+        .build()?
+        .try_deserialize()
+}
+
+fn router(state: SharedState) -> Router {
+    let spic = Router::new()
+        .route("/auth/rest/login", post(login))
+        .route("/auth/rest/logout", get(logout))
+        .route("/Units/rest/", get(units_count))
+        .route("/Units/rest/GetAllUnits", get(units))
+        .route("/UnitGroups/rest/GetAllUnitGroups", get(unit_groups))
+        .route("/MessagesService/rest/GetMessages", post(messages))
+        .route("/OnlineDataService/rest/Subscribe", post(subscribe))
+        .route("/OnlineDataService/rest/GetOnlineData", post(online_data));
+
+    let base_path = state.config.base_path.trim_end_matches('/').to_string();
+    let spic = spic.with_state(state);
+
+    if base_path.is_empty() {
+        spic
+    } else {
+        Router::new().nest(&base_path, spic)
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = load_config()?;
+    let address = format!("{}:{}", config.address, config.port);
+
+    let state = Arc::new(MockState {
+        fleet: Fleet::new(config.fleet.clone(), Utc::now()),
+        rng: Mutex::new(StdRng::seed_from_u64(config.fleet.seed)),
+        sessions: Mutex::new(HashMap::new()),
+        subscriptions: Mutex::new(HashMap::new()),
+        config,
+    });
+
+    // Port 0 picks a free port, the line below tells which one
+    let listener = tokio::net::TcpListener::bind(&address).await?;
+    println!(
+        "Mock SPIC with {} units listening on http://{}{}",
+        state.fleet.units.len(),
+        listener.local_addr()?,
+        state.config.base_path
+    );
+
+    axum::serve(listener, router(state))
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+            println!("Shutdown requested");
+        })
+        .await?;
+
+    Ok(())
+}
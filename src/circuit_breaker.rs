@@ -0,0 +1,176 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::rdl_config::CircuitBreakerConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BreakerState {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    /// One probe request is out, the others keep failing fast
+    HalfOpen { probe_started: Instant },
+}
+
+/// Stops calling SPIC after `failure_threshold` failed operations in a row.
+/// While open, one probe is let through every `open_duration`; its success
+/// closes the breaker again.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    state: Mutex<BreakerState>,
+    failure_threshold: u32,
+    open_duration: Duration,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: &CircuitBreakerConfig) -> Self {
+        CircuitBreaker {
+            state: Mutex::new(BreakerState::Closed { failures: 0 }),
+            failure_threshold: config.failure_threshold.max(1),
+            open_duration: Duration::from_millis(config.open_duration_ms),
+        }
+    }
+
+    /// Whether a request may go out now, otherwise the time until the next probe.
+    pub fn allow(&self) -> Result<(), Duration> {
+        self.allow_at(Instant::now())
+    }
+
+    fn allow_at(&self, now: Instant) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap();
+
+        match *state {
+            BreakerState::Closed { .. } => Ok(()),
+            BreakerState::Open { until } if now < until => Err(until - now),
+            // A probe whose caller went away never reports back, so a stuck
+            // probe is replaced after another `open_duration`
+            BreakerState::HalfOpen { probe_started } if now < probe_started + self.open_duration => {
+                Err(probe_started + self.open_duration - now)
+            }
+            BreakerState::Open { .. } | BreakerState::HalfOpen { .. } => {
+                println!("SPIC circuit breaker half-open, probing");
+                *state = BreakerState::HalfOpen { probe_started: now };
+                Ok(())
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        if !matches!(*state, BreakerState::Closed { .. }) {
+            println!("SPIC is reachable again, circuit breaker closed");
+        }
+
+        *state = BreakerState::Closed { failures: 0 };
+    }
+
+    pub fn record_failure(&self) {
+        self.record_failure_at(Instant::now());
+    }
+
+    fn record_failure_at(&self, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+
+        let failures = match *state {
+            BreakerState::Closed { failures } => failures + 1,
+            _ => self.failure_threshold,
+        };
+
+        *state = if failures >= self.failure_threshold {
+            println!(
+                "SPIC circuit breaker open after {} failures, next probe in {} ms",
+                failures,
+                self.open_duration.as_millis()
+            );
+            BreakerState::Open {
+                until: now + self.open_duration,
+            }
+        } else {
+            BreakerState::Closed { failures }
+        };
+    }
+
+    /// True while requests fail fast, including while a probe is out.
+    pub fn is_open(&self) -> bool {
+        !matches!(*self.state.lock().unwrap(), BreakerState::Closed { .. })
+    }
+}
+
+#[cfg(test)]
+mod circuit_breaker_tests {
+    use super::*;
+
+    fn breaker(failure_threshold: u32) -> CircuitBreaker {
+        CircuitBreaker::new(&CircuitBreakerConfig {
+            failure_threshold,
+            open_duration_ms: 1000,
+        })
+    }
+
+    #[test]
+    fn test_opens_after_threshold() {
+        let breaker = breaker(3);
+        let now = Instant::now();
+
+        breaker.record_failure_at(now);
+        breaker.record_failure_at(now);
+        assert!(breaker.allow_at(now).is_ok());
+
+        breaker.record_failure_at(now);
+        assert!(breaker.is_open());
+        assert_eq!(breaker.allow_at(now), Err(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_success_resets_failures() {
+        let breaker = breaker(2);
+        let now = Instant::now();
+
+        breaker.record_failure_at(now);
+        breaker.record_success();
+        breaker.record_failure_at(now);
+
+        assert!(!breaker.is_open());
+    }
+
+    #[test]
+    fn test_single_probe_after_open_duration() {
+        let breaker = breaker(1);
+        let now = Instant::now();
+        breaker.record_failure_at(now);
+
+        let later = now + Duration::from_secs(1);
+        assert!(breaker.allow_at(later).is_ok());
+        assert!(breaker.allow_at(later).is_err());
+        assert!(breaker.is_open());
+
+        breaker.record_success();
+        assert!(!breaker.is_open());
+        assert!(breaker.allow_at(later).is_ok());
+    }
+
+    #[test]
+    fn test_failed_probe_reopens() {
+        let breaker = breaker(3);
+        let now = Instant::now();
+        for _ in 0..3 {
+            breaker.record_failure_at(now);
+        }
+
+        let later = now + Duration::from_secs(1);
+        assert!(breaker.allow_at(later).is_ok());
+        breaker.record_failure_at(later);
+
+        assert_eq!(breaker.allow_at(later), Err(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_stuck_probe_is_replaced() {
+        let breaker = breaker(1);
+        let now = Instant::now();
+        breaker.record_failure_at(now);
+
+        assert!(breaker.allow_at(now + Duration::from_secs(1)).is_ok());
+        assert!(breaker.allow_at(now + Duration::from_secs(2)).is_ok());
+    }
+}
@@ -1,15 +1,42 @@
 use core::time::Duration;
 use sqlx::sqlite::SqlitePoolOptions;
-use sqlx::{FromRow, SqlitePool};
-use crate::rdl_config::DatabaseConfig;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{FromRow, Row, SqlitePool};
+use crate::rdl_config::{DatabaseConfig, DEFAULT_ACCOUNT};
 use crate::conf as conf;
 use lazy_static::lazy_static;
 use crate::spic_client::SpicUnit as SpicData;
+use crate::spic_client::{SpicUnitGroup, TrackPoint};
+use crate::telematics::{Position, Unit};
+use std::collections::HashMap;
 
 lazy_static! {
     static ref DATABASE_CONFIG: DatabaseConfig = conf!(database);
 }
 
+const SPIC_DATA_TABLE: &str = "CREATE TABLE IF NOT EXISTS spic_data (
+    account TEXT NOT NULL DEFAULT 'default',
+    unit_id INTEGER NOT NULL,
+    brand TEXT,
+    model TEXT,
+    state_number TEXT,
+    color TEXT,
+    company_id INTEGER,
+    description TEXT,
+    garage_number TEXT,
+    name TEXT NOT NULL,
+    olson_id TEXT,
+    owner TEXT,
+    power INTEGER,
+    registration TEXT,
+    unit_type_id INTEGER,
+    vin_number TEXT,
+    year INTEGER,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (account, unit_id)
+)";
+
 macro_rules! execute_query {
     ($query:expr, $caller:literal, $pool:expr) => {
         $query.execute($pool).await.map_err(|e| DBError::SqliteError{
@@ -27,7 +54,7 @@ pub struct Database {
 
 pub async fn create_sqlite_pool() -> Result<SqlitePool, DBError> {
     let database_url = &DATABASE_CONFIG.sqlite.log_path;
-    SqlitePool::connect(&database_url).await.map_err(map_db_error("create_sqlite_pool", database_url.to_string()))
+    SqlitePool::connect(database_url).await.map_err(map_db_error("create_sqlite_pool", database_url.to_string()))
 }
 
 async fn set_pragma(pool: &SqlitePool, pragma: &str) -> Result<(), DBError> {
@@ -62,41 +89,77 @@ impl Database {
             source: e,
         })?;
 
-        set_pragma(&pool, "PRAGMA journal_mode = WAL").await?;
+        set_pragma(&pool, "journal_mode = WAL").await?;
 
         dbg!(&pool);
 
         Ok(Self { pool })
     }
 
+    /// Private in-memory database, a single connection keeps it alive.
+    #[cfg(test)]
+    pub async fn in_memory() -> Result<Self, DBError> {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await
+            .map_err(map_db_error("Database::in_memory", String::new()))?;
 
+        Ok(Self { pool })
+    }
 
     async fn init_spic(&self) -> Result<(), DBError> {
-        let query = sqlx::query(
-            "CREATE TABLE IF NOT EXISTS spic_data (
-                unit_id INTEGER PRIMARY KEY,
-                brand TEXT,
-                model TEXT,
-                state_number TEXT,
-                color TEXT,
-                company_id INTEGER,
-                description TEXT,
-                garage_number TEXT,
-                name TEXT NOT NULL,
-                olson_id TEXT,
-                owner TEXT,
-                power INTEGER,
-                registration TEXT,
-                unit_type_id INTEGER,
-                vin_number TEXT,
-                year INTEGER,
-                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-            )");
+        let columns = self.table_columns("spic_data").await?;
 
-            execute_query!(query, "Database::init_spic", &self.pool);
+        if !columns.is_empty() && !columns.iter().any(|column| column == "account") {
+            return self.migrate_spic_data().await;
+        }
 
-            Ok(())
+        execute_query!(sqlx::query(SPIC_DATA_TABLE), "Database::init_spic", &self.pool);
+
+        Ok(())
+    }
+
+    /// `spic_data` created before accounts existed is keyed by unit id alone.
+    /// It is created again with the `account` column, its rows belong to the
+    /// default account.
+    async fn migrate_spic_data(&self) -> Result<(), DBError> {
+        println!("Adding accounts to spic_data, existing rows belong to '{}'", DEFAULT_ACCOUNT);
+
+        let mut tx = self.pool.begin().await.map_err(map_db_error("Database::migrate_spic_data", String::new()))?;
+
+        for statement in ["ALTER TABLE spic_data RENAME TO spic_data_legacy", SPIC_DATA_TABLE] {
+            sqlx::query(statement)
+                .execute(&mut *tx)
+                .await
+                .map_err(map_db_error("Database::migrate_spic_data", statement.to_string()))?;
+        }
+
+        sqlx::query(
+            "INSERT INTO spic_data (
+                account, unit_id, brand, model, state_number, color, company_id,
+                description, garage_number, name, olson_id, owner, power,
+                registration, unit_type_id, vin_number, year, created_at, updated_at
+            ) SELECT
+                ?, unit_id, brand, model, state_number, color, company_id,
+                description, garage_number, name, olson_id, owner, power,
+                registration, unit_type_id, vin_number, year, created_at, updated_at
+            FROM spic_data_legacy")
+            .bind(DEFAULT_ACCOUNT)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_error("Database::migrate_spic_data", "copy rows".to_string()))?;
+
+        sqlx::query("DROP TABLE spic_data_legacy")
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_error("Database::migrate_spic_data", "drop spic_data_legacy".to_string()))?;
+
+        tx.commit().await.map_err(map_db_error("Database::migrate_spic_data", String::new()))?;
+
+        Ok(())
     }
 
     async fn init_logging(&self) -> Result<(), DBError> {
@@ -114,13 +177,271 @@ impl Database {
         Ok(())
     }
 
+    async fn init_unit_groups(&self) -> Result<(), DBError> {
+        let query = sqlx::query(
+            "CREATE TABLE IF NOT EXISTS unit_group_members (
+                account TEXT NOT NULL DEFAULT 'default',
+                group_id INTEGER NOT NULL,
+                unit_id INTEGER NOT NULL,
+                PRIMARY KEY (account, group_id, unit_id)
+            )");
+
+        execute_query!(query, "Database::init_unit_groups", &self.pool);
+
+        let query = sqlx::query(
+            "CREATE TABLE IF NOT EXISTS unit_groups (
+                account TEXT NOT NULL DEFAULT 'default',
+                group_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                description TEXT,
+                company_id INTEGER,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (account, group_id)
+            )");
+
+        execute_query!(query, "Database::init_unit_groups", &self.pool);
+
+        Ok(())
+    }
+
+    async fn init_track_points(&self) -> Result<(), DBError> {
+        let query = sqlx::query(
+            "CREATE TABLE IF NOT EXISTS track_points (
+                account TEXT NOT NULL DEFAULT 'default',
+                unit_id INTEGER NOT NULL,
+                message_time TIMESTAMP NOT NULL,
+                latitude REAL NOT NULL,
+                longitude REAL NOT NULL,
+                altitude_meters INTEGER,
+                angle INTEGER,
+                speed INTEGER,
+                satellites_count INTEGER,
+                navigation_system_type TEXT,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (account, unit_id, message_time)
+            )");
+
+        execute_query!(query, "Database::init_track_points", &self.pool);
+
+        Ok(())
+    }
+
+    /// Units and positions of every provider, in the shape of `telematics`.
+    async fn init_fleet(&self) -> Result<(), DBError> {
+        let units = sqlx::query(
+            "CREATE TABLE IF NOT EXISTS fleet_units (
+                provider TEXT NOT NULL,
+                account TEXT NOT NULL,
+                unit_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                registration TEXT,
+                vin TEXT,
+                model TEXT,
+                extras TEXT,
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (provider, account, unit_id)
+            )");
+        execute_query!(units, "Database::init_fleet", &self.pool);
+
+        let positions = sqlx::query(
+            "CREATE TABLE IF NOT EXISTS fleet_positions (
+                provider TEXT NOT NULL,
+                account TEXT NOT NULL,
+                unit_id INTEGER NOT NULL,
+                time TIMESTAMP NOT NULL,
+                latitude REAL NOT NULL,
+                longitude REAL NOT NULL,
+                speed REAL,
+                course INTEGER,
+                altitude REAL,
+                satellites INTEGER,
+                is_valid INTEGER NOT NULL,
+                extras TEXT,
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (provider, account, unit_id)
+            )");
+        execute_query!(positions, "Database::init_fleet", &self.pool);
+
+        Ok(())
+    }
+
+    async fn table_columns(&self, table: &str) -> Result<Vec<String>, DBError> {
+        sqlx::query_scalar("SELECT name FROM pragma_table_info(?)")
+            .bind(table)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(map_db_error("Database::table_columns", table.to_string()))
+    }
+
     pub async fn init(&self) -> Result<(), DBError> {
         self.init_spic().await?;
+        self.init_unit_groups().await?;
+        self.init_track_points().await?;
+        self.init_fleet().await?;
         self.init_logging().await?;
 
         Ok(())
     }
 
+    /// Stores track points, points already stored for the same unit and time are replaced.
+    pub async fn save_track(&self, account: &str, points: &[TrackPoint]) -> Result<(), DBError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_error("Database::save_track", format!("{} points", points.len())))?;
+
+        for point in points {
+            point.insert_or_update(account, &mut tx).await?;
+        }
+
+        tx.commit().await.map_err(map_db_error("Database::save_track", format!("{} points", points.len())))?;
+
+        Ok(())
+    }
+
+    /// Stores the unit list of a provider account, units already stored are updated.
+    pub async fn save_fleet_units(&self, provider: &str, account: &str, units: &[Unit]) -> Result<(), DBError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_error("Database::save_fleet_units", format!("{} units", units.len())))?;
+
+        for unit in units {
+            sqlx::query(
+                "INSERT INTO fleet_units (
+                    provider, account, unit_id, name, registration, vin, model, extras
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(provider, account, unit_id) DO UPDATE SET
+                    name = excluded.name,
+                    registration = excluded.registration,
+                    vin = excluded.vin,
+                    model = excluded.model,
+                    extras = excluded.extras,
+                    updated_at = CURRENT_TIMESTAMP")
+                .bind(provider)
+                .bind(account)
+                .bind(unit.id)
+                .bind(&unit.name)
+                .bind(&unit.registration)
+                .bind(&unit.vin)
+                .bind(&unit.model)
+                .bind(serde_json::Value::Object(unit.extras.clone()).to_string())
+                .execute(&mut *tx)
+                .await
+                .map_err(map_db_error("Upsert on fleet_units", format!("{}/{}: {}", provider, account, unit.id)))?;
+        }
+
+        tx.commit().await.map_err(map_db_error("Database::save_fleet_units", format!("{} units", units.len())))?;
+
+        Ok(())
+    }
+
+    /// Units of a provider account stored by the last successful `save_fleet_units`.
+    pub async fn load_fleet_units(&self, provider: &str, account: &str) -> Result<Vec<Unit>, DBError> {
+        let rows = sqlx::query("SELECT * FROM fleet_units WHERE provider = ? AND account = ? ORDER BY unit_id")
+            .bind(provider)
+            .bind(account)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(map_db_error("Database::load_fleet_units", format!("{}/{}", provider, account)))?;
+
+        rows.iter()
+            .map(unit_from_row)
+            .collect::<Result<_, _>>()
+            .map_err(map_db_error("Database::load_fleet_units", format!("{}/{}", provider, account)))
+    }
+
+    /// Stores the latest position of each unit of a provider account.
+    pub async fn save_positions(&self, provider: &str, account: &str, positions: &HashMap<i64, Position>) -> Result<(), DBError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_error("Database::save_positions", format!("{} units", positions.len())))?;
+
+        for position in positions.values() {
+            sqlx::query(
+                "INSERT INTO fleet_positions (
+                    provider, account, unit_id, time, latitude, longitude, speed,
+                    course, altitude, satellites, is_valid, extras
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                ON CONFLICT(provider, account, unit_id) DO UPDATE SET
+                    time = excluded.time,
+                    latitude = excluded.latitude,
+                    longitude = excluded.longitude,
+                    speed = excluded.speed,
+                    course = excluded.course,
+                    altitude = excluded.altitude,
+                    satellites = excluded.satellites,
+                    is_valid = excluded.is_valid,
+                    extras = excluded.extras,
+                    updated_at = CURRENT_TIMESTAMP")
+                .bind(provider)
+                .bind(account)
+                .bind(position.unit_id)
+                .bind(position.time)
+                .bind(position.latitude)
+                .bind(position.longitude)
+                .bind(position.speed)
+                .bind(position.course)
+                .bind(position.altitude)
+                .bind(position.satellites)
+                .bind(position.is_valid)
+                .bind(serde_json::Value::Object(position.extras.clone()).to_string())
+                .execute(&mut *tx)
+                .await
+                .map_err(map_db_error("Upsert on fleet_positions", format!("{}/{}: {}", provider, account, position.unit_id)))?;
+        }
+
+        tx.commit().await.map_err(map_db_error("Database::save_positions", format!("{} units", positions.len())))?;
+
+        Ok(())
+    }
+
+    /// Last stored positions of the units, units without a stored position
+    /// are left out.
+    pub async fn load_positions(&self, provider: &str, account: &str, unit_ids: &[i64]) -> Result<HashMap<i64, Position>, DBError> {
+        let mut positions = HashMap::with_capacity(unit_ids.len());
+
+        for unit_id in unit_ids {
+            let row = sqlx::query("SELECT * FROM fleet_positions WHERE provider = ? AND account = ? AND unit_id = ?")
+                .bind(provider)
+                .bind(account)
+                .bind(unit_id)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(map_db_error("Database::load_positions", format!("unit_id: {}", unit_id)))?;
+
+            if let Some(row) = row {
+                let position = position_from_row(&row)
+                    .map_err(map_db_error("Database::load_positions", format!("unit_id: {}", unit_id)))?;
+                positions.insert(*unit_id, position);
+            }
+        }
+
+        Ok(positions)
+    }
+
+    /// Replaces the stored groups of the account and their members with the
+    /// given ones, groups that are gone from SPIC are deleted.
+    pub async fn save_unit_groups(&self, account: &str, groups: &[SpicUnitGroup]) -> Result<(), DBError> {
+        let group_ids = serde_json::Value::from(groups.iter().map(|group| group.id).collect::<Vec<_>>()).to_string();
+        let data = format!("account: {}, groups: {}", account, group_ids);
+        let mut tx = self.pool.begin().await.map_err(map_db_error("Database::save_unit_groups", data.clone()))?;
+
+        sqlx::query("DELETE FROM unit_group_members WHERE account = ?")
+            .bind(account)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_error("Delete on unit_group_members", data.clone()))?;
+
+        sqlx::query("DELETE FROM unit_groups WHERE account = ? AND group_id NOT IN (SELECT value FROM json_each(?))")
+            .bind(account)
+            .bind(&group_ids)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_error("Delete on unit_groups", data.clone()))?;
+
+        for group in groups {
+            group.insert_or_update(account, &mut tx).await?;
+        }
+
+        tx.commit().await.map_err(map_db_error("Database::save_unit_groups", data))?;
+
+        Ok(())
+    }
+
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -145,21 +466,23 @@ fn map_db_error(caller: &'static str, data: String) -> impl FnOnce(sqlx::Error)
     }
 }
 
-trait DatabaseOperations {
+#[allow(async_fn_in_trait)]
+pub trait DatabaseOperations {
 
-    async fn  insert(&self, pool: &SqlitePool) -> Result<(), DBError> ;
+    async fn  insert(&self, account: &str, pool: &SqlitePool) -> Result<(), DBError> ;
 
-    async fn insert_or_update(&self, pool: &SqlitePool) -> Result<(), DBError>;
+    async fn insert_or_update(&self, account: &str, pool: &SqlitePool) -> Result<(), DBError>;
 
-    async fn  delete(&self, pool: &SqlitePool) -> Result<(), DBError>;
+    async fn  delete(&self, account: &str, pool: &SqlitePool) -> Result<(), DBError>;
 
-    async fn  select(&self, pool: &SqlitePool) -> Result<(), DBError>;
+    async fn  select(&self, account: &str, pool: &SqlitePool) -> Result<(), DBError>;
 }
 
 impl DatabaseOperations for SpicData {
-    async fn insert(&self, pool: &SqlitePool) -> Result<(), DBError> {
+    async fn insert(&self, account: &str, pool: &SqlitePool) -> Result<(), DBError> {
         let query = sqlx::query(
             "INSERT INTO spic_data (
+                account,
                 unit_id,
                 brand,
                 model,
@@ -177,13 +500,14 @@ impl DatabaseOperations for SpicData {
                 vin_number,
                 year
             ) VALUES (
-                ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?
-            )").bind(&self.id)
+                ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?
+            )").bind(account)
+            .bind(self.id)
             .bind(&self.brand)
             .bind(&self.model)
             .bind(&self.state_number)
             .bind(&self.color)
-            .bind(&self.company_id)
+            .bind(self.company_id)
             .bind(&self.description)
             .bind(&self.garage_number)
             .bind(&self.name)
@@ -191,7 +515,7 @@ impl DatabaseOperations for SpicData {
             .bind(&self.owner)
             .bind(&self.power)
             .bind(&self.registration)
-            .bind(&self.type_id)
+            .bind(self.type_id)
             .bind(&self.vin)
             .bind(&self.year);
 
@@ -200,16 +524,303 @@ impl DatabaseOperations for SpicData {
             Ok(())
     }
     
-    async fn insert_or_update(&self, pool: &SqlitePool) {
-        todo!()
+    async fn insert_or_update(&self, account: &str, pool: &SqlitePool) -> Result<(), DBError> {
+        let mut conn = pool.acquire().await.map_err(map_db_error("Upsert on spicdata", format!("{:?}", self)))?;
+
+        self.store(account, &mut conn).await
     }
     
-    async fn  delete(&self, pool: &SqlitePool) {
+    async fn  delete(&self, _account: &str, _pool: &SqlitePool) -> Result<(), DBError> {
         todo!()
     }
     
-    async fn  select(&self, pool: &SqlitePool) {
+    async fn  select(&self, _account: &str, _pool: &SqlitePool) -> Result<(), DBError> {
         todo!()
     }
 }
 
+impl SpicData {
+    /// Inserts or updates the unit as part of the account's unit list.
+    async fn store(&self, account: &str, tx: &mut sqlx::SqliteConnection) -> Result<(), DBError> {
+        let query = sqlx::query(
+            "INSERT INTO spic_data (
+                account,
+                unit_id,
+                brand,
+                model,
+                state_number,
+                color,
+                company_id,
+                description,
+                garage_number,
+                name,
+                olson_id,
+                owner,
+                power,
+                registration,
+                unit_type_id,
+                vin_number,
+                year
+            ) VALUES (
+                ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?
+            ) ON CONFLICT(account, unit_id) DO UPDATE SET
+                brand = excluded.brand,
+                model = excluded.model,
+                state_number = excluded.state_number,
+                color = excluded.color,
+                company_id = excluded.company_id,
+                description = excluded.description,
+                garage_number = excluded.garage_number,
+                name = excluded.name,
+                olson_id = excluded.olson_id,
+                owner = excluded.owner,
+                power = excluded.power,
+                registration = excluded.registration,
+                unit_type_id = excluded.unit_type_id,
+                vin_number = excluded.vin_number,
+                year = excluded.year,
+                updated_at = CURRENT_TIMESTAMP").bind(account)
+            .bind(self.id)
+            .bind(&self.brand)
+            .bind(&self.model)
+            .bind(&self.state_number)
+            .bind(&self.color)
+            .bind(self.company_id)
+            .bind(&self.description)
+            .bind(&self.garage_number)
+            .bind(&self.name)
+            .bind(&self.olson_id)
+            .bind(&self.owner)
+            .bind(&self.power)
+            .bind(&self.registration)
+            .bind(self.type_id)
+            .bind(&self.vin)
+            .bind(&self.year);
+
+            query.execute(&mut *tx).await.map_err(map_db_error("Upsert on spicdata", format!("{:?}", self)))?;
+
+            Ok(())
+    }
+}
+
+impl SpicUnitGroup {
+    async fn insert_or_update(&self, account: &str, tx: &mut sqlx::SqliteConnection) -> Result<(), DBError> {
+        sqlx::query(
+            "INSERT INTO unit_groups (
+                account,
+                group_id,
+                name,
+                description,
+                company_id
+            ) VALUES (
+                ?, ?, ?, ?, ?
+            ) ON CONFLICT(account, group_id) DO UPDATE SET
+                name = excluded.name,
+                description = excluded.description,
+                company_id = excluded.company_id,
+                updated_at = CURRENT_TIMESTAMP").bind(account)
+            .bind(self.id)
+            .bind(&self.name)
+            .bind(&self.description)
+            .bind(self.company_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_error("Upsert on unit_groups", format!("{:?}", self)))?;
+
+        for unit_id in &self.unit_ids {
+            sqlx::query("INSERT OR IGNORE INTO unit_group_members (account, group_id, unit_id) VALUES (?, ?, ?)")
+                .bind(account)
+                .bind(self.id)
+                .bind(unit_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(map_db_error("Insert on unit_group_members", format!("{:?}", self)))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl TrackPoint {
+    async fn insert_or_update(&self, account: &str, tx: &mut sqlx::SqliteConnection) -> Result<(), DBError> {
+        let navigation = &self.navigation;
+
+        sqlx::query(
+            "INSERT OR REPLACE INTO track_points (
+                account,
+                unit_id,
+                message_time,
+                latitude,
+                longitude,
+                altitude_meters,
+                angle,
+                speed,
+                satellites_count,
+                navigation_system_type
+            ) VALUES (
+                ?, ?, ?, ?, ?, ?, ?, ?, ?, ?
+            )").bind(account)
+            .bind(self.unit_id)
+            .bind(self.time)
+            .bind(navigation.location.latitude)
+            .bind(navigation.location.longitude)
+            .bind(navigation.altitude_meters)
+            .bind(navigation.angle)
+            .bind(navigation.speed)
+            .bind(navigation.satellites_count)
+            .bind(&navigation.navigation_system_type)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_error("Insert on track_points", format!("{:?}", self)))?;
+
+        Ok(())
+    }
+}
+
+fn extras_from_row(row: &SqliteRow) -> Result<serde_json::Map<String, serde_json::Value>, sqlx::Error> {
+    let extras: Option<String> = row.try_get("extras")?;
+
+    Ok(extras
+        .and_then(|extras| serde_json::from_str(&extras).ok())
+        .unwrap_or_default())
+}
+
+fn unit_from_row(row: &SqliteRow) -> Result<Unit, sqlx::Error> {
+    Ok(Unit {
+        id: row.try_get("unit_id")?,
+        name: row.try_get("name")?,
+        registration: row.try_get("registration")?,
+        vin: row.try_get("vin")?,
+        model: row.try_get("model")?,
+        extras: extras_from_row(row)?,
+    })
+}
+
+fn position_from_row(row: &SqliteRow) -> Result<Position, sqlx::Error> {
+    Ok(Position {
+        unit_id: row.try_get("unit_id")?,
+        time: row.try_get("time")?,
+        latitude: row.try_get("latitude")?,
+        longitude: row.try_get("longitude")?,
+        speed: row.try_get("speed")?,
+        course: row.try_get("course")?,
+        altitude: row.try_get("altitude")?,
+        satellites: row.try_get("satellites")?,
+        is_valid: row.try_get("is_valid")?,
+        extras: extras_from_row(row)?,
+    })
+}
+
+#[cfg(test)]
+mod database_tests {
+    use super::*;
+
+    fn group(id: i32, unit_ids: Vec<i32>) -> SpicUnitGroup {
+        SpicUnitGroup {
+            id,
+            name: format!("Column {}", id),
+            description: None,
+            company_id: Some(1),
+            unit_ids,
+        }
+    }
+
+    async fn group_members(db: &Database) -> Vec<(String, i32, i32)> {
+        sqlx::query_as("SELECT account, group_id, unit_id FROM unit_group_members ORDER BY account, group_id, unit_id")
+            .fetch_all(&db.pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_save_unit_groups_replaces_stale_groups() {
+        let db = Database::in_memory().await.unwrap();
+        db.init().await.unwrap();
+
+        db.save_unit_groups("first", &[group(1, vec![10, 11]), group(2, vec![20])]).await.unwrap();
+        db.save_unit_groups("first", &[group(1, vec![11, 12])]).await.unwrap();
+
+        let group_ids: Vec<i32> = sqlx::query_scalar("SELECT group_id FROM unit_groups")
+            .fetch_all(&db.pool)
+            .await
+            .unwrap();
+
+        assert_eq!(group_ids, [1]);
+        assert_eq!(group_members(&db).await, [("first".to_string(), 1, 11), ("first".to_string(), 1, 12)]);
+    }
+
+    #[tokio::test]
+    async fn test_unit_groups_of_accounts_are_kept_apart() {
+        let db = Database::in_memory().await.unwrap();
+        db.init().await.unwrap();
+
+        db.save_unit_groups("first", &[group(1, vec![10])]).await.unwrap();
+        db.save_unit_groups("second", &[group(1, vec![20])]).await.unwrap();
+        db.save_unit_groups("second", &[]).await.unwrap();
+
+        assert_eq!(group_members(&db).await, [("first".to_string(), 1, 10)]);
+    }
+
+    #[tokio::test]
+    async fn test_init_moves_spic_data_to_default_account() {
+        let db = Database::in_memory().await.unwrap();
+
+        for statement in [
+            "CREATE TABLE spic_data (
+                unit_id INTEGER PRIMARY KEY,
+                brand TEXT,
+                model TEXT,
+                state_number TEXT,
+                color TEXT,
+                company_id INTEGER,
+                description TEXT,
+                garage_number TEXT,
+                name TEXT NOT NULL,
+                olson_id TEXT,
+                owner TEXT,
+                power INTEGER,
+                registration TEXT,
+                unit_type_id INTEGER,
+                vin_number TEXT,
+                year INTEGER,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            )",
+            "INSERT INTO spic_data (unit_id, name, vin_number, year, created_at)
+                VALUES (82697, 'Bus 12', 'XTA210930Y2765432', 2019, '2024-05-01 10:00:00')",
+            "INSERT INTO spic_data (unit_id, name) VALUES (82698, 'Bus 14')",
+        ] {
+            sqlx::query(statement).execute(&db.pool).await.unwrap();
+        }
+
+        db.init().await.unwrap();
+        // A second start finds nothing left to migrate
+        db.init().await.unwrap();
+
+        type Row = (String, i32, String, Option<String>, Option<i32>);
+        let rows: Vec<Row> =
+            sqlx::query_as("SELECT account, unit_id, name, vin_number, year FROM spic_data ORDER BY unit_id")
+                .fetch_all(&db.pool)
+                .await
+                .unwrap();
+        let created_at: String = sqlx::query_scalar("SELECT created_at FROM spic_data WHERE unit_id = 82697")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            rows,
+            [
+                (DEFAULT_ACCOUNT.to_string(), 82697, "Bus 12".to_string(), Some("XTA210930Y2765432".to_string()), Some(2019)),
+                (DEFAULT_ACCOUNT.to_string(), 82698, "Bus 14".to_string(), None, None),
+            ]
+        );
+        assert_eq!(created_at, "2024-05-01 10:00:00");
+        assert!(db.table_columns("spic_data_legacy").await.unwrap().is_empty());
+
+        sqlx::query("INSERT INTO spic_data (account, unit_id, name) VALUES ('second', 82697, 'Bus 12')")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+    }
+}
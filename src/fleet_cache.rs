@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use crate::database::{DBError, Database};
+use crate::telematics::{Position, TelematicsError, TelematicsProvider, Unit};
+
+#[derive(thiserror::Error, Debug)]
+pub enum FleetCacheError {
+    #[error(transparent)]
+    Provider(#[from] TelematicsError),
+
+    #[error(transparent)]
+    Database(#[from] DBError),
+}
+
+/// Result of a read, `stale` is set when it came from the local cache
+/// because the provider was down.
+#[derive(Debug)]
+pub struct Cached<T> {
+    pub data: T,
+    pub stale: bool,
+}
+
+/// Reads units and last positions from a provider and keeps a copy in
+/// SQLite, tagged with the provider and account. When the provider is down,
+/// overloaded or its retries ran out, the copy is served instead.
+pub struct FleetCache<'a, P: TelematicsProvider> {
+    provider: &'a P,
+    db: &'a Database,
+}
+
+impl<'a, P: TelematicsProvider> FleetCache<'a, P> {
+    pub fn new(provider: &'a P, db: &'a Database) -> Self {
+        FleetCache { provider, db }
+    }
+
+    pub async fn units(&self) -> Result<Cached<Vec<Unit>>, FleetCacheError> {
+        let (name, account) = (self.provider.provider(), self.provider.account());
+
+        match self.provider.units().await {
+            Ok(units) => {
+                self.db.save_fleet_units(name, account, &units).await?;
+                Ok(Cached { data: units, stale: false })
+            }
+            Err(e) if e.is_outage() || self.provider.is_unavailable() => {
+                println!("{} is down ({}), serving cached unit list", name, e);
+                Ok(Cached {
+                    data: self.db.load_fleet_units(name, account).await?,
+                    stale: true,
+                })
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Last known positions of the units. Units the provider returned
+    /// nothing for are left out, as they are in the cached answer.
+    pub async fn last_positions(&self, unit_ids: &[i64]) -> Result<Cached<HashMap<i64, Position>>, FleetCacheError> {
+        let (name, account) = (self.provider.provider(), self.provider.account());
+
+        match self.provider.positions(unit_ids).await {
+            Ok(positions) => {
+                self.db.save_positions(name, account, &positions).await?;
+                Ok(Cached {
+                    data: positions,
+                    stale: false,
+                })
+            }
+            Err(e) if e.is_outage() || self.provider.is_unavailable() => {
+                println!("{} is down ({}), serving cached positions", name, e);
+                Ok(Cached {
+                    data: self.db.load_positions(name, account, unit_ids).await?,
+                    stale: true,
+                })
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod fleet_cache_tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+    use crate::spic_client::SpicError;
+
+    /// Answers until `down` is set, then fails the way an overloaded SPIC
+    /// does, without the breaker being open yet.
+    struct FakeProvider {
+        down: AtomicBool,
+    }
+
+    impl TelematicsProvider for FakeProvider {
+        fn provider(&self) -> &'static str {
+            "fake"
+        }
+
+        fn account(&self) -> &str {
+            "first"
+        }
+
+        fn is_unavailable(&self) -> bool {
+            false
+        }
+
+        async fn authenticate(&self) -> Result<(), TelematicsError> {
+            Ok(())
+        }
+
+        async fn logout(&self) -> Result<(), TelematicsError> {
+            Ok(())
+        }
+
+        async fn units(&self) -> Result<Vec<Unit>, TelematicsError> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(SpicError::ServiceBusy { caller: "FakeProvider::units" }.into());
+            }
+
+            Ok(vec![Unit {
+                id: 7,
+                name: "KAMAZ 007".to_string(),
+                registration: None,
+                vin: None,
+                model: None,
+                extras: Default::default(),
+            }])
+        }
+
+        async fn positions(&self, unit_ids: &[i64]) -> Result<HashMap<i64, Position>, TelematicsError> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(SpicError::ServiceBusy { caller: "FakeProvider::positions" }.into());
+            }
+
+            Ok(unit_ids
+                .iter()
+                .map(|unit_id| {
+                    let position = Position {
+                        unit_id: *unit_id,
+                        time: chrono::DateTime::from_timestamp(1735972469, 0).unwrap(),
+                        latitude: 56.8389,
+                        longitude: 60.6057,
+                        speed: Some(42.0),
+                        course: None,
+                        altitude: None,
+                        satellites: None,
+                        is_valid: true,
+                        extras: Default::default(),
+                    };
+                    (*unit_id, position)
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_serves_stale_cache_on_outage() {
+        let db = Database::in_memory().await.unwrap();
+        db.init().await.unwrap();
+        let provider = FakeProvider { down: AtomicBool::new(false) };
+        let cache = FleetCache::new(&provider, &db);
+
+        let fresh = cache.units().await.unwrap();
+        assert!(!fresh.stale);
+        cache.last_positions(&[7]).await.unwrap();
+
+        provider.down.store(true, Ordering::SeqCst);
+
+        let units = cache.units().await.unwrap();
+        let positions = cache.last_positions(&[7]).await.unwrap();
+
+        assert!(units.stale && positions.stale);
+        assert_eq!(units.data[0].name, "KAMAZ 007");
+        assert_eq!(positions.data[&7].speed, Some(42.0));
+    }
+}
@@ -0,0 +1,17 @@
+//! Code shared by the sc-rdl binaries.
+
+pub mod circuit_breaker;
+pub mod database;
+pub mod fleet_cache;
+pub mod logger_storage;
+pub mod ms_date;
+pub mod rate_limit;
+pub mod rdl_config;
+pub mod retry;
+pub mod secret;
+pub mod session;
+pub mod spic_client;
+pub mod telematics;
+pub mod token_store;
+pub mod transport;
+pub mod wialon_client;
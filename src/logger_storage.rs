@@ -1,3 +1,6 @@
+// The logging macros are placeholders until the database logger exists
+#![allow(unused_macros)]
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
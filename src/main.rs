@@ -1,13 +1,10 @@
-mod rdl_config;
-mod spic_client;
-mod database;
-mod logger_storage;
+use sc_rdl_rust::rdl_config::init_config;
 
-use rdl_config::init_config;
-use spic_client::OnlineData;
-use tokio;
-
-use crate::spic_client::init_client;
+use sc_rdl_rust::fleet_cache::FleetCache;
+use sc_rdl_rust::spic_client::{init_clients, SpicClient};
+use sc_rdl_rust::telematics::TelematicsProvider;
+use sc_rdl_rust::wialon_client::{init_wialon_clients, WialonClient};
+use sc_rdl_rust::{database, telematics};
 
 
 #[tokio::main]
@@ -15,31 +12,192 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 
     init_config()?;
-    let config = rdl_config::CONFIG.read().unwrap();
-
-    // let datetime = Utc::now().to_rfc3339();
-    let test_unit_id = 82697;
 
     let db = database::Database::new().await?;
 
     db.init().await?;
 
 
-    let mut client = init_client();
-    if client.authenticate().await? {
-        println!("Authentication successful");
+    let clients = init_clients()?;
+    // A failed login is not fatal, requests log in again and the fleet
+    // cache covers for a provider that is down.
+    for client in &clients {
+        match client.authenticate().await {
+            Ok(()) => println!("Authentication successful for account {}", client.account()),
+            Err(e) => println!("Authentication failed for account {}: {}", client.account(), e),
+        }
+    }
+
+    let wialon_clients = init_wialon_clients()?;
+    for client in &wialon_clients {
+        if let Err(e) = client.authenticate().await {
+            println!("Authentication failed for Wialon account {}: {}", client.account(), e);
+        }
+    }
+
+    let token_refresh = clients
+        .iter()
+        .map(|client| client.spawn_token_refresh())
+        .collect::<Vec<_>>();
+    let subscription_renewal = clients
+        .iter()
+        .map(|client| client.spawn_subscription_renewal())
+        .collect::<Vec<_>>();
+
+    let result = tokio::select! {
+        result = run_all(&clients, &wialon_clients, &db) => result,
+        _ = shutdown_signal() => {
+            println!("Shutdown requested");
+            Ok(())
+        }
+    };
+
+    for handle in token_refresh.into_iter().chain(subscription_renewal) {
+        handle.abort();
+    }
+
+    for client in &clients {
+        if client.logout_on_exit() {
+            if let Err(e) = client.logout().await {
+                println!("Logout of account {} failed: {}", client.account(), e);
+            }
+        }
+
+        for (class, stats) in client.rate_limit_stats() {
+            println!(
+                "{} {:?} requests: {}, waited for a slot {} ms on average, {} ms at most",
+                client.account(),
+                class,
+                stats.requests,
+                stats.average_wait().as_millis(),
+                stats.max_wait.as_millis()
+            );
+        }
+    }
+
+    for client in &wialon_clients {
+        if let Err(e) = client.logout().await {
+            println!("Logout of Wialon account {} failed: {}", client.account(), e);
+        }
+    }
+
+    result
+}
+
+/// Ctrl+C, or SIGTERM from the service manager on unix.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => println!("Unable to listen for SIGTERM: {}", e),
+        }
+    }
+
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        println!("Unable to listen for Ctrl+C: {}", e);
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Runs every account, a failed one does not stop the others.
+async fn run_all(
+    clients: &[SpicClient],
+    wialon_clients: &[WialonClient],
+    db: &database::Database,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut failed = Vec::new();
+
+    for client in clients {
+        println!("Account {}", client.account());
+        if let Err(e) = run(client, db).await {
+            println!("Account {} failed: {}", client.account(), e);
+            failed.push(client.account().to_string());
+        }
+    }
+
+    for client in wialon_clients {
+        println!("Wialon account {}", client.account());
+        if let Err(e) = sync_fleet(client, db).await {
+            println!("Wialon account {} failed: {}", client.account(), e);
+            failed.push(format!("wialon.{}", client.account()));
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(())
     } else {
-        println!("Authentication failed");
-        return Err("Authentication failed".into());
+        Err(format!(
+            "{} of {} accounts failed: {}",
+            failed.len(),
+            clients.len() + wialon_clients.len(),
+            failed.join(", ")
+        )
+        .into())
     }
-    println!("Number of units: {}", client.number_of_units().await?);
+}
+
+/// Refreshes the stored units and positions of any provider, returns the units.
+async fn sync_fleet<P: TelematicsProvider>(
+    provider: &P,
+    db: &database::Database,
+) -> Result<Vec<telematics::Unit>, Box<dyn std::error::Error>> {
+    let cache = FleetCache::new(provider, db);
 
-    let unit_list = client.unit_list().await?;
+    let units = cache.units().await?;
+    if units.stale {
+        println!("{} is down, using {} cached units", provider.provider(), units.data.len());
+    }
 
-    let unit_ids = unit_list.iter().map(|unit| unit.id).collect::<Vec<i32>>();
+    let unit_ids = units.data.iter().map(|unit| unit.id).collect::<Vec<i64>>();
 
-    dbg!(&unit_list[12]);
+    let positions = cache.last_positions(&unit_ids).await?;
+    println!(
+        "Positions for {} of {} units{}",
+        positions.data.len(),
+        unit_ids.len(),
+        if positions.stale { " (stale, from cache)" } else { "" }
+    );
 
-    Ok(())
+    Ok(units.data)
+}
+
+async fn run(client: &SpicClient, db: &database::Database) -> Result<(), Box<dyn std::error::Error>> {
+    match client.number_of_units().await {
+        Ok(count) => println!("Number of units: {}", count),
+        Err(e) => println!("Unable to get the number of units: {}", e),
+    }
 
+    let unit_list = sync_fleet(client, db).await?;
+
+    // Unit groups are not verified against SPIC yet, a failure only skips them
+    match client.unit_groups().await {
+        Ok(unit_groups) => {
+            db.save_unit_groups(client.account(), &unit_groups).await?;
+            println!("Unit groups saved: {}", unit_groups.len());
+        }
+        Err(e) => println!("Unable to get unit groups: {}", e),
+    }
+
+    if let Some(unit_id) = unit_list.first().and_then(|unit| i32::try_from(unit.id).ok()) {
+        let to = chrono::Utc::now();
+        // GetMessages paging is not verified against SPIC yet, see track_history
+        match client.track_history(unit_id, to - chrono::Duration::hours(1), to).await {
+            Ok(track) => {
+                db.save_track(client.account(), &track).await?;
+                println!("Track points saved for unit {}: {}", unit_id, track.len());
+            }
+            Err(e) => println!("Unable to get the track of unit {}: {}", unit_id, e),
+        }
+    }
+
+    Ok(())
 }
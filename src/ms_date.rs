@@ -0,0 +1,42 @@
+use chrono::{DateTime, FixedOffset};
+
+/// Parses a WCF JSON date such as `/Date(1735972469975+0300)/`. The number is
+/// milliseconds since the Unix epoch in UTC (negative before 1970), the optional
+/// `±hhmm` suffix is the offset of the server, kept for displaying.
+pub fn parse_ms_date(value: &str) -> Option<DateTime<FixedOffset>> {
+    let inner = value.strip_prefix("/Date(")?.strip_suffix(")/")?;
+
+    // Skip the first char so a leading minus of a negative epoch is not
+    // mistaken for the offset sign
+    let (ms, offset) = match inner
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| *c == '+' || *c == '-')
+    {
+        Some((i, _)) => (&inner[..i], Some(&inner[i..])),
+        None => (inner, None),
+    };
+
+    let ms = ms.parse::<i64>().ok()?;
+
+    let offset = match offset {
+        Some(offset) => {
+            let (sign, digits) = offset.split_at(1);
+            if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            let hours: i32 = digits[..2].parse().ok()?;
+            let minutes: i32 = digits[2..].parse().ok()?;
+            let seconds = hours * 3600 + minutes * 60;
+
+            if sign == "-" {
+                FixedOffset::west_opt(seconds)?
+            } else {
+                FixedOffset::east_opt(seconds)?
+            }
+        }
+        None => FixedOffset::east_opt(0).unwrap(),
+    };
+
+    DateTime::from_timestamp_millis(ms).map(|date| date.with_timezone(&offset))
+}
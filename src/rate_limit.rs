@@ -0,0 +1,254 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+use crate::rdl_config::{RateLimitConfig, RateLimitsConfig};
+
+/// Groups of SPIC endpoints sharing one limiter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EndpointClass {
+    /// Login, logout and session probes
+    Auth,
+    /// Unit list, unit count, unit groups and track history
+    Units,
+    /// Online data subscribe and get
+    OnlineData,
+}
+
+/// Classic token bucket: up to `capacity` requests at once, refilled with
+/// `rate` tokens per second.
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    capacity: f64,
+    rate: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, burst: u32) -> Self {
+        let capacity = burst.max(1) as f64;
+
+        TokenBucket {
+            tokens: capacity,
+            capacity,
+            rate,
+            updated: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated = now;
+    }
+
+    /// Takes a token, or returns how long to wait until one is available.
+    fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+}
+
+/// How long requests of one endpoint class waited for a slot.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WaitStats {
+    pub requests: u64,
+    pub total_wait: Duration,
+    pub max_wait: Duration,
+}
+
+impl WaitStats {
+    pub fn average_wait(&self) -> Duration {
+        if self.requests == 0 {
+            Duration::ZERO
+        } else {
+            self.total_wait / self.requests as u32
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct WaitMetrics {
+    requests: AtomicU64,
+    total_wait_us: AtomicU64,
+    max_wait_us: AtomicU64,
+}
+
+impl WaitMetrics {
+    fn record(&self, wait: Duration) {
+        let wait_us = wait.as_micros() as u64;
+
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.total_wait_us.fetch_add(wait_us, Ordering::Relaxed);
+        self.max_wait_us.fetch_max(wait_us, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> WaitStats {
+        WaitStats {
+            requests: self.requests.load(Ordering::Relaxed),
+            total_wait: Duration::from_micros(self.total_wait_us.load(Ordering::Relaxed)),
+            max_wait: Duration::from_micros(self.max_wait_us.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Request rate and in-flight limit for one endpoint class.
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// `None` when the rate is not limited
+    bucket: Option<Mutex<TokenBucket>>,
+    in_flight: Arc<Semaphore>,
+    metrics: WaitMetrics,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        let bucket = (config.requests_per_second > 0.0)
+            .then(|| Mutex::new(TokenBucket::new(config.requests_per_second, config.burst)));
+
+        RateLimiter {
+            bucket,
+            in_flight: Arc::new(Semaphore::new(config.max_in_flight.max(1))),
+            metrics: WaitMetrics::default(),
+        }
+    }
+
+    /// Waits for a free in-flight slot and a token. The request counts as in
+    /// flight until the returned permit is dropped.
+    pub async fn acquire(&self) -> OwnedSemaphorePermit {
+        let started = Instant::now();
+
+        let permit = Arc::clone(&self.in_flight)
+            .acquire_owned()
+            .await
+            .expect("rate limiter semaphore is never closed");
+
+        if let Some(bucket) = &self.bucket {
+            // Waiting while holding the bucket keeps callers in FIFO order
+            let mut bucket = bucket.lock().await;
+            while let Err(wait) = bucket.try_take(Instant::now()) {
+                tokio::time::sleep(wait).await;
+            }
+        }
+
+        self.metrics.record(started.elapsed());
+
+        permit
+    }
+
+    pub fn wait_stats(&self) -> WaitStats {
+        self.metrics.snapshot()
+    }
+}
+
+/// One limiter per endpoint class, shared by all clones of a client.
+#[derive(Debug)]
+pub struct RateLimits {
+    auth: RateLimiter,
+    units: RateLimiter,
+    online_data: RateLimiter,
+}
+
+impl RateLimits {
+    pub fn new(config: &RateLimitsConfig) -> Self {
+        RateLimits {
+            auth: RateLimiter::new(&config.auth),
+            units: RateLimiter::new(&config.units),
+            online_data: RateLimiter::new(&config.online_data),
+        }
+    }
+
+    pub fn limiter(&self, class: EndpointClass) -> &RateLimiter {
+        match class {
+            EndpointClass::Auth => &self.auth,
+            EndpointClass::Units => &self.units,
+            EndpointClass::OnlineData => &self.online_data,
+        }
+    }
+
+    pub async fn acquire(&self, class: EndpointClass) -> OwnedSemaphorePermit {
+        self.limiter(class).acquire().await
+    }
+
+    pub fn wait_stats(&self) -> Vec<(EndpointClass, WaitStats)> {
+        [EndpointClass::Auth, EndpointClass::Units, EndpointClass::OnlineData]
+            .into_iter()
+            .map(|class| (class, self.limiter(class).wait_stats()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod rate_limit_tests {
+    use super::*;
+
+    fn config(requests_per_second: f64, burst: u32, max_in_flight: usize) -> RateLimitConfig {
+        RateLimitConfig {
+            requests_per_second,
+            burst,
+            max_in_flight,
+        }
+    }
+
+    #[test]
+    fn test_bucket_allows_burst_then_waits() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(10.0, 2);
+        bucket.updated = now;
+
+        assert!(bucket.try_take(now).is_ok());
+        assert!(bucket.try_take(now).is_ok());
+
+        let wait = bucket.try_take(now).unwrap_err();
+        assert!(wait > Duration::from_millis(90) && wait <= Duration::from_millis(100));
+
+        assert!(bucket.try_take(now + Duration::from_millis(100)).is_ok());
+    }
+
+    #[test]
+    fn test_bucket_does_not_exceed_capacity() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(10.0, 2);
+        bucket.updated = now;
+
+        bucket.refill(now + Duration::from_secs(60));
+
+        assert_eq!(bucket.tokens, 2.0);
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_limit() {
+        let limiter = RateLimiter::new(&config(0.0, 1, 2));
+
+        let first = limiter.acquire().await;
+        let _second = limiter.acquire().await;
+        assert_eq!(limiter.in_flight.available_permits(), 0);
+
+        drop(first);
+        let _third = limiter.acquire().await;
+        assert_eq!(limiter.wait_stats().requests, 3);
+    }
+
+    #[tokio::test]
+    async fn test_wait_is_recorded() {
+        let limiter = RateLimiter::new(&config(50.0, 1, 10));
+
+        drop(limiter.acquire().await);
+        drop(limiter.acquire().await);
+
+        let stats = limiter.wait_stats();
+        assert_eq!(stats.requests, 2);
+        assert!(stats.max_wait >= Duration::from_millis(15));
+        assert!(stats.average_wait() <= stats.max_wait);
+    }
+}
@@ -1,9 +1,16 @@
-use config::{Config, File, ConfigError, Environment};   
+use config::{Config, File, ConfigError};   
+use chrono_tz::Tz;
 
-use std::{env, sync::{Arc, RwLock}};
+use std::{collections::BTreeMap, env, sync::RwLock};
 use serde::Deserialize;
 use lazy_static::lazy_static;
 
+use crate::secret::PasswordSource;
+
+/// Name of the `[spic]` account unless `account` is set there. Data stored
+/// before accounts existed belongs to it.
+pub const DEFAULT_ACCOUNT: &str = "default";
+
 lazy_static! {
     pub static ref CONFIG: RwLock<Option<Settings>> = RwLock::new(None);
 }
@@ -13,7 +20,7 @@ lazy_static! {
 #[macro_export]
 macro_rules! conf {
     ($name:ident) => {
-        crate::rdl_config::get_config().as_ref().unwrap().$name.clone()
+        $crate::rdl_config::get_config().as_ref().unwrap().$name.clone()
     };
 }
 
@@ -33,10 +40,284 @@ pub enum DebugLevel {
     Off,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SpicEndpointsConfig {
+    pub login: String,
+    pub logout: String,
+    pub units_number: String,
+    pub unit_list: String,
+    pub unit_groups: String,
+    pub track_history: String,
+    pub online_data_subscribe: String,
+    pub online_data_get: String,
+}
+
+impl Default for SpicEndpointsConfig {
+    fn default() -> Self {
+        SpicEndpointsConfig {
+            login: "/auth/rest/login".to_string(),
+            logout: "/auth/rest/logout".to_string(),
+            units_number: "/Units/rest/".to_string(),
+            unit_list: "/Units/rest/GetAllUnits".to_string(),
+            unit_groups: "/UnitGroups/rest/GetAllUnitGroups".to_string(),
+            track_history: "/MessagesService/rest/GetMessages".to_string(),
+            online_data_subscribe: "/OnlineDataService/rest/Subscribe".to_string(),
+            online_data_get: "/OnlineDataService/rest/GetOnlineData".to_string(),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct SpicConfig {
+    /// Name the data of this account is stored under
+    #[serde(default = "default_account")]
+    pub account: String,
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    #[serde(default)]
+    pub endpoints: SpicEndpointsConfig,
+    pub login: String,
+    pub password: PasswordSource,
+    /// Olson id sent on login and used for showing timestamps
+    #[serde(default = "default_timezone")]
+    pub timezone: Tz,
+    #[serde(default = "default_culture")]
+    pub culture: String,
+    #[serde(default = "default_culture")]
+    pub ui_culture: String,
+    #[serde(default = "default_subscription_timeout_ms")]
+    pub subscription_timeout_ms: u64,
+    #[serde(default = "default_subscription_poll_interval_ms")]
+    pub subscription_poll_interval_ms: u64,
+    #[serde(default = "default_online_data_chunk_size")]
+    pub online_data_chunk_size: usize,
+    #[serde(default = "default_token_refresh_margin_s")]
+    pub token_refresh_margin_s: i64,
+    #[serde(default = "default_history_window_hours")]
+    pub history_window_hours: i64,
+    #[serde(default = "default_history_page_size")]
+    pub history_page_size: usize,
+    #[serde(default)]
+    pub connect_timeout_ms: Option<u64>,
+    /// End the session on exit. Set it to false to keep the stored token
+    /// for the next run instead of logging in again.
+    #[serde(default = "default_logout_on_exit")]
+    pub logout_on_exit: bool,
+    /// Proxy URL for all SPIC requests
+    #[serde(default)]
+    pub proxy: Option<String>,
+    /// Sent instead of the default `sc-rdl-rust/<version>`
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// Live requests, or recording/replaying them as fixtures
+    #[serde(default)]
+    pub transport: TransportConfig,
+    /// Further accounts by name, everything they do not set is taken from `[spic]`
+    #[serde(default)]
+    pub accounts: BTreeMap<String, SpicAccountConfig>,
+}
+
+/// How SPIC requests are sent. Fixtures of each account are kept in a
+/// subdirectory of `fixtures` named after the account.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum TransportConfig {
+    #[default]
+    Live,
+    /// Sends requests and saves every request/response pair
+    Record { fixtures: String },
+    /// Answers requests from the saved pairs without network access
+    Replay { fixtures: String },
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct SpicAccountConfig {
     pub login: String,
-    pub password: String,
+    pub password: PasswordSource,
+    #[serde(default)]
+    pub base_url: Option<String>,
+}
+
+fn default_account() -> String {
+    DEFAULT_ACCOUNT.to_string()
+}
+
+impl SpicConfig {
+    /// The `[spic]` account followed by each of `[spic.accounts]`, every one
+    /// as a complete config of its own.
+    pub fn account_configs(&self) -> Vec<SpicConfig> {
+        let primary = SpicConfig {
+            accounts: BTreeMap::new(),
+            ..self.clone()
+        };
+
+        let others = self.accounts.iter().map(|(name, account)| SpicConfig {
+            account: name.clone(),
+            login: account.login.clone(),
+            password: account.password.clone(),
+            base_url: account.base_url.clone().unwrap_or_else(|| self.base_url.clone()),
+            ..primary.clone()
+        });
+
+        std::iter::once(primary.clone()).chain(others).collect()
+    }
+}
+
+fn default_logout_on_exit() -> bool {
+    true
+}
+
+fn default_history_page_size() -> usize {
+    1000
+}
+
+fn default_history_window_hours() -> i64 {
+    24
+}
+
+fn default_culture() -> String {
+    "ru-ru".to_string()
+}
+
+fn default_timezone() -> Tz {
+    chrono_tz::Asia::Yekaterinburg
+}
+
+fn default_base_url() -> String {
+    "http://login.scout-gps.ru/spic".to_string()
+}
+
+fn default_token_refresh_margin_s() -> i64 {
+    600
+}
+
+fn default_online_data_chunk_size() -> usize {
+    100
+}
+
+fn default_subscription_poll_interval_ms() -> u64 {
+    500
+}
+
+fn default_subscription_timeout_ms() -> u64 {
+    15000
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RetryConfig {
+    pub max_attempts: u32,
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+    pub multiplier: f64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: 5,
+            initial_delay_ms: 500,
+            max_delay_ms: 10000,
+            multiplier: 2.0,
+        }
+    }
+}
+
+/// Limits for one class of SPIC endpoints. `requests_per_second = 0`
+/// disables the rate limit, the in-flight limit always applies.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RateLimitConfig {
+    pub requests_per_second: f64,
+    pub burst: u32,
+    pub max_in_flight: usize,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RateLimitsConfig {
+    pub auth: RateLimitConfig,
+    pub units: RateLimitConfig,
+    pub online_data: RateLimitConfig,
+}
+
+impl Default for RateLimitsConfig {
+    fn default() -> Self {
+        RateLimitsConfig {
+            auth: RateLimitConfig {
+                requests_per_second: 1.0,
+                burst: 2,
+                max_in_flight: 1,
+            },
+            units: RateLimitConfig {
+                requests_per_second: 5.0,
+                burst: 5,
+                max_in_flight: 4,
+            },
+            online_data: RateLimitConfig {
+                requests_per_second: 10.0,
+                burst: 10,
+                max_in_flight: 8,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct CircuitBreakerConfig {
+    /// Failed operations in a row before SPIC is considered down
+    pub failure_threshold: u32,
+    /// Pause between probes while SPIC is down
+    pub open_duration_ms: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        CircuitBreakerConfig {
+            failure_threshold: 5,
+            open_duration_ms: 30000,
+        }
+    }
+}
+
+/// Where SPIC sessions are cached between runs.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "backend", rename_all = "snake_case")]
+pub enum TokenStoreConfig {
+    /// OS credential store, entries are named after `service` and the login
+    Keyring { service: String },
+    /// Encrypted JSON file. The key is read as hex from the `key_env`
+    /// variable if it is set, otherwise from `key_path`, created on first use
+    EncryptedFile {
+        path: String,
+        key_path: String,
+        #[serde(default)]
+        key_env: Option<String>,
+    },
+    /// Nothing is kept between runs
+    Memory,
+}
+
+impl Default for TokenStoreConfig {
+    fn default() -> Self {
+        TokenStoreConfig::Keyring {
+            service: "sc-rdl".to_string(),
+        }
+    }
+}
+
+/// Wialon account, one `[wialon.<account>]` section each.
+#[derive(Debug, Deserialize, Clone)]
+pub struct WialonConfig {
+    #[serde(default = "default_wialon_base_url")]
+    pub base_url: String,
+    /// Access token of the account, resolved like the SPIC password
+    pub token: PasswordSource,
+}
+
+fn default_wialon_base_url() -> String {
+    "https://hst-api.wialon.com".to_string()
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -60,6 +341,7 @@ pub struct SqliteConfig {
 pub struct ServerConfig {
     pub address: String,
     pub port: String,
+    /// Milliseconds, also the total timeout of each SPIC and Wialon request.
     pub request_timeout: i32,
 }
 
@@ -68,6 +350,16 @@ pub struct ServerConfig {
 pub struct Settings { 
     pub debug_level: DebugLevel,
     pub spic: SpicConfig,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitsConfig,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    #[serde(default)]
+    pub token_store: TokenStoreConfig,
+    #[serde(default)]
+    pub wialon: BTreeMap<String, WialonConfig>,
     pub server: ServerConfig,
     pub database: DatabaseConfig
 }
@@ -76,13 +368,12 @@ impl Settings {
     pub fn new() -> Result<Self, ConfigError> {
 
         let args: Vec<String> = env::args().collect();
-        let file_name: &str;
 
-        if  args.contains(&"--debug".to_string()) {
-            file_name = "./config/rdl-debug.toml";
+        let file_name = if  args.contains(&"--debug".to_string()) {
+            "./config/rdl-debug.toml"
         } else {
-            file_name = "./config/rdl.toml";
-        }
+            "./config/rdl.toml"
+        };
 
         let config_builder = Config::builder().
             add_source(File::with_name(file_name)).
@@ -108,3 +399,57 @@ pub fn init_config() -> Result<(), ConfigError> {
 pub fn get_config() -> std::sync::RwLockReadGuard<'static, Option<Settings>> {
     CONFIG.read().unwrap()
 }
+
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+    use config::FileFormat;
+
+    /// `config/rdl.toml` as it was before any of the optional settings existed.
+    const BASELINE_CONFIG: &str = r#"
+debug_level = "debug"
+
+[spic]
+
+login = "user@example.com"
+password = "not-a-real-password"
+
+[database]
+
+db_type = "sqlite"
+
+[database.sqlite]
+
+path = "data/rdl.db"
+log_path = "data/rdl.db"
+pool_acquire_timeout = 10
+pool_idle_timeout = 3600
+minimum_connection_pool_size = 3
+maximum_connection_pool_size = 20
+pool_max_lifetime = 43200
+
+[server]
+
+address = "127.0.0.1"
+port = "4339"
+request_timeout = 5000
+"#;
+
+    fn parse(toml: &str) -> Settings {
+        Config::builder()
+            .add_source(File::from_str(toml, FileFormat::Toml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_baseline_config_is_accepted() {
+        let settings = parse(BASELINE_CONFIG);
+
+        assert_eq!(settings.spic.login, "user@example.com");
+        assert_eq!(settings.retry.max_attempts, 5);
+        assert_eq!(settings.server.request_timeout, 5000);
+    }
+}
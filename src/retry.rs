@@ -0,0 +1,195 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::rdl_config::RetryConfig;
+
+/// Errors that know whether the failed operation is worth another attempt.
+pub trait Retryable {
+    fn is_retryable(&self) -> bool;
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+    multiplier: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new(5, Duration::from_millis(500), Duration::from_secs(10), 2.0)
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, initial_delay: Duration, max_delay: Duration, multiplier: f64) -> Self {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            initial_delay,
+            max_delay,
+            multiplier: multiplier.max(1.0),
+        }
+    }
+
+    pub fn from_config(config: &RetryConfig) -> Self {
+        RetryPolicy::new(
+            config.max_attempts,
+            Duration::from_millis(config.initial_delay_ms),
+            Duration::from_millis(config.max_delay_ms),
+            config.multiplier,
+        )
+    }
+
+    /// Upper bound of the delay before the next attempt, `attempt` starts at 1.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.powi(attempt.saturating_sub(1) as i32);
+        let delay = self.initial_delay.as_secs_f64() * factor;
+
+        if !delay.is_finite() || delay >= self.max_delay.as_secs_f64() {
+            self.max_delay
+        } else {
+            Duration::from_secs_f64(delay)
+        }
+    }
+
+    /// "Equal jitter": half of the backoff is fixed, the other half is random,
+    /// so parallel callers do not hammer the server in lockstep.
+    fn jittered_backoff(&self, attempt: u32) -> Duration {
+        let delay = self.backoff(attempt);
+        let half = delay / 2;
+        let jitter = rand::thread_rng().gen_range(0..=half.as_millis() as u64);
+
+        half + Duration::from_millis(jitter)
+    }
+}
+
+/// Runs `operation` until it succeeds, returns a non-retryable error or the
+/// policy runs out of attempts. The last error is returned as is.
+pub async fn with_retry<F, Fut, T, E>(policy: &RetryPolicy, caller: &'static str, mut operation: F) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Retryable + Display,
+{
+    let mut attempt = 1;
+
+    loop {
+        match operation().await {
+            Ok(value) => return Ok(value),
+            Err(e) if e.is_retryable() && attempt < policy.max_attempts => {
+                let delay = policy.jittered_backoff(attempt);
+                println!(
+                    "{} failed (attempt {}/{}): {}, retrying in {} ms",
+                    caller,
+                    attempt,
+                    policy.max_attempts,
+                    e,
+                    delay.as_millis()
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod retry_tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[derive(Debug)]
+    struct TestError(bool);
+
+    impl Display for TestError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "test error, retryable: {}", self.0)
+        }
+    }
+
+    impl Retryable for TestError {
+        fn is_retryable(&self) -> bool {
+            self.0
+        }
+    }
+
+    fn instant_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy::new(max_attempts, Duration::ZERO, Duration::ZERO, 2.0)
+    }
+
+    #[test]
+    fn test_backoff_grows_and_caps() {
+        let policy = RetryPolicy::new(10, Duration::from_millis(100), Duration::from_secs(1), 2.0);
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(5), Duration::from_secs(1));
+        assert_eq!(policy.backoff(100), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_jitter_stays_within_bounds() {
+        let policy = RetryPolicy::new(10, Duration::from_millis(100), Duration::from_secs(1), 2.0);
+
+        for _ in 0..100 {
+            let delay = policy.jittered_backoff(2);
+            assert!(delay >= Duration::from_millis(100));
+            assert!(delay <= Duration::from_millis(200));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_success() {
+        let calls = Cell::new(0);
+
+        let result = with_retry(&instant_policy(5), "test", || {
+            calls.set(calls.get() + 1);
+            let attempt = calls.get();
+            async move {
+                if attempt < 3 {
+                    Err(TestError(true))
+                } else {
+                    Ok(attempt)
+                }
+            }
+        })
+        .await;
+
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_fatal_error_is_not_retried() {
+        let calls = Cell::new(0);
+
+        let result: Result<(), TestError> = with_retry(&instant_policy(5), "test", || {
+            calls.set(calls.get() + 1);
+            async { Err(TestError(false)) }
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        let calls = Cell::new(0);
+
+        let result: Result<(), TestError> = with_retry(&instant_policy(4), "test", || {
+            calls.set(calls.get() + 1);
+            async { Err(TestError(true)) }
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 4);
+    }
+}
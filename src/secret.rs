@@ -0,0 +1,164 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use keyring::Entry;
+use serde::{Deserialize, Serialize};
+
+/// String that never shows up in `Debug` or `Display` output, for passwords
+/// and session ids. `expose` gives the value where it is really needed.
+/// Serializing writes the value itself, only serialize into secret storage.
+#[derive(Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Secret(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Secret(value)
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("***")
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SecretError {
+    #[error("Environment variable {0} with the password is not set")]
+    MissingEnv(String),
+
+    #[error("Unable to read password file {path}: {source}")]
+    File {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Unable to read password from the keyring: {0}")]
+    Keyring(#[from] keyring::Error),
+
+    #[error("Password from {0} is empty")]
+    Empty(&'static str),
+}
+
+/// Where the SPIC password comes from. A bare string in the config is still
+/// accepted as the password itself, but should only be used for local tests.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum PasswordSource {
+    Plain(Secret),
+    Located(PasswordLocation),
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum PasswordLocation {
+    /// Environment variable holding the password
+    Env { var: String },
+    /// File holding the password, e.g. a Docker or systemd secret.
+    /// Surrounding whitespace is trimmed
+    File { path: PathBuf },
+    /// OS credential store, the entry is named after `service` and the login
+    Keyring { service: String },
+}
+
+impl PasswordSource {
+    pub fn resolve(&self, login: &str) -> Result<Secret, SecretError> {
+        let (password, origin) = match self {
+            PasswordSource::Plain(password) => (password.clone(), "config"),
+            PasswordSource::Located(PasswordLocation::Env { var }) => (
+                Secret::new(std::env::var(var).map_err(|_| SecretError::MissingEnv(var.clone()))?),
+                "environment",
+            ),
+            PasswordSource::Located(PasswordLocation::File { path }) => {
+                let password = std::fs::read_to_string(path).map_err(|source| SecretError::File {
+                    path: path.clone(),
+                    source,
+                })?;
+                (Secret::new(password.trim()), "file")
+            }
+            PasswordSource::Located(PasswordLocation::Keyring { service }) => {
+                (Secret::new(Entry::new(service, login)?.get_password()?), "keyring")
+            }
+        };
+
+        if password.is_empty() {
+            return Err(SecretError::Empty(origin));
+        }
+
+        Ok(password)
+    }
+}
+
+#[cfg(test)]
+mod secret_tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_is_redacted() {
+        let secret = Secret::new("hunter2");
+
+        assert_eq!(format!("{:?}", secret), "Secret(***)");
+        assert_eq!(format!("{}", secret), "***");
+        assert_eq!(secret.expose(), "hunter2");
+    }
+
+    #[test]
+    fn test_password_source_from_config() {
+        #[derive(Deserialize)]
+        struct Config {
+            password: PasswordSource,
+        }
+
+        let plain: Config = serde_json::from_str(r#"{"password": "hunter2"}"#).unwrap();
+        assert!(matches!(plain.password, PasswordSource::Plain(_)));
+
+        let env: Config =
+            serde_json::from_str(r#"{"password": {"source": "env", "var": "SPIC_PASSWORD"}}"#).unwrap();
+        assert!(matches!(
+            env.password,
+            PasswordSource::Located(PasswordLocation::Env { .. })
+        ));
+    }
+
+    #[test]
+    fn test_resolve_from_env_and_file() {
+        std::env::set_var("SC_RDL_TEST_PASSWORD", "from-env");
+        let env = PasswordSource::Located(PasswordLocation::Env {
+            var: "SC_RDL_TEST_PASSWORD".to_string(),
+        });
+        assert_eq!(env.resolve("login").unwrap().expose(), "from-env");
+
+        let path = std::env::temp_dir().join(format!("sc-rdl-password-{}", std::process::id()));
+        std::fs::write(&path, "from-file\n").unwrap();
+        let file = PasswordSource::Located(PasswordLocation::File { path: path.clone() });
+        assert_eq!(file.resolve("login").unwrap().expose(), "from-file");
+        std::fs::remove_file(&path).unwrap();
+
+        let missing = PasswordSource::Located(PasswordLocation::Env {
+            var: "SC_RDL_TEST_PASSWORD_MISSING".to_string(),
+        });
+        assert!(matches!(missing.resolve("login"), Err(SecretError::MissingEnv(_))));
+    }
+}
@@ -0,0 +1,186 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::Duration;
+
+use crate::circuit_breaker::CircuitBreaker;
+use crate::rdl_config::CircuitBreakerConfig;
+use crate::retry::{with_retry, RetryPolicy, Retryable};
+
+/// What [`Session::authorized`] needs to know about the errors of a client.
+pub trait SessionError: Retryable + std::fmt::Display {
+    /// The provider rejected the session, a new login may help.
+    fn is_rejected(&self) -> bool;
+
+    /// `caller` was not sent, the provider is considered down.
+    fn circuit_open(caller: &'static str, retry_in: Duration) -> Self;
+}
+
+/// Login state shared by all clones of a provider client: the session token,
+/// the circuit breaker and a lock serializing logins. `generation` is bumped
+/// on every login, so callers that saw the same rejected session log in
+/// only once.
+#[derive(Debug)]
+pub struct Session<T> {
+    token: RwLock<Option<T>>,
+    generation: AtomicU64,
+    login_lock: tokio::sync::Mutex<()>,
+    breaker: CircuitBreaker,
+}
+
+impl<T: Clone> Session<T> {
+    pub fn new(circuit_breaker: &CircuitBreakerConfig) -> Self {
+        Session {
+            token: RwLock::new(None),
+            generation: AtomicU64::new(0),
+            login_lock: tokio::sync::Mutex::new(()),
+            breaker: CircuitBreaker::new(circuit_breaker),
+        }
+    }
+
+    pub fn token(&self) -> Option<T> {
+        self.token.read().unwrap().clone()
+    }
+
+    /// Swaps in the token of a new login.
+    pub fn set(&self, token: T) {
+        *self.token.write().unwrap() = Some(token);
+        self.generation.fetch_add(1, Ordering::AcqRel);
+    }
+
+    pub fn take(&self) -> Option<T> {
+        self.token.write().unwrap().take()
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Held while logging in.
+    pub async fn lock_login(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.login_lock.lock().await
+    }
+
+    /// True while the provider is considered down and requests fail fast.
+    pub fn is_unavailable(&self) -> bool {
+        self.breaker.is_open()
+    }
+
+    /// Runs `login` unless another caller already logged in since
+    /// `seen_generation`.
+    pub async fn relogin<E, F, Fut>(&self, seen_generation: u64, login: F) -> Result<(), E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<(), E>>,
+    {
+        let _login = self.lock_login().await;
+
+        if self.generation() != seen_generation {
+            return Ok(());
+        }
+
+        login().await
+    }
+
+    /// Runs a request with retries. If the provider rejects the session,
+    /// `relogin` gets the generation the request saw and the request is
+    /// replayed once. Fails fast while the provider is considered down.
+    pub async fn authorized<R, E, F, Fut, L, LoginFut>(
+        &self,
+        retry: &RetryPolicy,
+        caller: &'static str,
+        mut request: F,
+        relogin: L,
+    ) -> Result<R, E>
+    where
+        E: SessionError,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<R, E>>,
+        L: FnOnce(u64) -> LoginFut,
+        LoginFut: Future<Output = Result<(), E>>,
+    {
+        self.breaker.allow().map_err(|retry_in| E::circuit_open(caller, retry_in))?;
+
+        let generation = self.generation();
+
+        let result = match with_retry(retry, caller, &mut request).await {
+            Err(e) if e.is_rejected() => {
+                println!("Session rejected in {}, logging in again", caller);
+                match relogin(generation).await {
+                    Ok(()) => with_retry(retry, caller, request).await,
+                    Err(e) => Err(e),
+                }
+            }
+            result => result,
+        };
+
+        // Only outages count, any answer proves the provider is up
+        match &result {
+            Err(e) if e.is_retryable() => self.breaker.record_failure(),
+            _ => self.breaker.record_success(),
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod session_tests {
+    use super::*;
+    use std::sync::atomic::AtomicU32;
+
+    #[derive(Debug, thiserror::Error)]
+    enum TestError {
+        #[error("rejected")]
+        Rejected,
+
+        #[error("circuit open")]
+        CircuitOpen,
+    }
+
+    impl Retryable for TestError {
+        fn is_retryable(&self) -> bool {
+            false
+        }
+    }
+
+    impl SessionError for TestError {
+        fn is_rejected(&self) -> bool {
+            matches!(self, TestError::Rejected)
+        }
+
+        fn circuit_open(_caller: &'static str, _retry_in: Duration) -> Self {
+            TestError::CircuitOpen
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejected_request_is_replayed_after_one_login() {
+        let session = Session::<String>::new(&CircuitBreakerConfig::default());
+        let retry = RetryPolicy::new(1, Duration::ZERO, Duration::ZERO, 1.0);
+        let sent = AtomicU32::new(0);
+
+        let result = session
+            .authorized(
+                &retry,
+                "test",
+                || async {
+                    match (sent.fetch_add(1, Ordering::AcqRel), session.token()) {
+                        (0, _) => Err(TestError::Rejected),
+                        (_, token) => Ok(token),
+                    }
+                },
+                |generation| {
+                    session.relogin(generation, || async {
+                        session.set("token".to_string());
+                        Ok(())
+                    })
+                },
+            )
+            .await;
+
+        assert_eq!(result.unwrap().as_deref(), Some("token"));
+        assert_eq!(sent.load(Ordering::Acquire), 2);
+        assert_eq!(session.generation(), 1);
+    }
+}
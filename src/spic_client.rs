@@ -1,94 +1,77 @@
 use chrono::Duration;
 #[allow(unused, unused_variables, dead_code)]
 // Работает? не трогай. Ретрай обязательно сделать, с проверкой того что вернули подписки, т.к у них есть задержка
-use chrono::{DateTime, TimeZone, Utc};
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Deserializer, Serialize};
 use std::{
-    borrow::Cow,
     collections::HashMap,
-    error::Error,
     future::Future,
-    sync::{Arc, Mutex},
+    sync::{Arc, RwLock},
+    time::Instant,
 };
 
-use keyring::Entry;
-
 use reqwest::{header, Client};
 use serde_json::json;
 
-use crate::rdl_config::{SpicConfig, CONFIG};
+use crate::rdl_config::{CircuitBreakerConfig, RateLimitsConfig, SpicConfig};
+use crate::rate_limit::{EndpointClass, RateLimits, WaitStats};
+use crate::retry::{with_retry, RetryPolicy, Retryable};
+use crate::secret::{Secret, SecretError};
+use crate::session::{Session, SessionError};
+use crate::telematics::{Position, TelematicsError, TelematicsProvider, Unit};
+use crate::transport::{Transport, TransportError, TransportMode};
+use crate::token_store::{self, MemoryTokenStore, StoredToken, TokenStore, TokenStoreError};
 use crate::conf as conf;
+use crate::ms_date;
 
-const DEFAULT_USER_AGENT: &'static str =
-    "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:133.0) Gecko/20100101 Firefox/133.0";
-const BASE_URL: &'static str = "http://login.scout-gps.ru/spic";
-const LOCAL_TIME_SHIFT: i64 = 5;
-
-fn store_auth_data(token: &str, expiration: &DateTime<Utc>) -> Result<(), Box<dyn Error>> {
-    let token_entry = Entry::new("sc-rdl", "auth_token")?;
-    let expiration_entry = Entry::new("sc-rdl", "auth_expiration")?;
+const DEFAULT_USER_AGENT: &str = concat!("sc-rdl-rust/", env!("CARGO_PKG_VERSION"));
+const SUBSCRIPTION_LIFETIME_MINUTES: i64 = 10;
+const SUBSCRIPTION_RENEW_MARGIN_SECONDS: i64 = 60;
+const SUBSCRIPTION_RENEWAL_INTERVAL_SECONDS: u64 = 20;
+const TOKEN_REFRESH_CHECK_INTERVAL_SECONDS: u64 = 60;
 
-    // Convert DateTime to string format
-    let expiration_str = expiration.to_rfc3339();
-
-    token_entry.set_password(token)?;
-    expiration_entry.set_password(&expiration_str)?;
-    Ok(())
+fn parse_ms_date(value: &str) -> Result<DateTime<FixedOffset>, SpicError> {
+    ms_date::parse_ms_date(value).ok_or_else(|| SpicError::DateParseError(value.to_string()))
 }
 
-fn get_stored_auth_data() -> Result<(String, DateTime<Utc>), Box<dyn Error>> {
-    let token_entry = Entry::new("sc-rdl", "auth_token")?;
-    let expiration_entry = Entry::new("sc-rdl", "auth_expiration")?;
-
-    let token = token_entry.get_password()?;
-    let expiration_str = expiration_entry.get_password()?;
-
-    // Parse string back to DateTime
-    let expiration = DateTime::parse_from_rfc3339(&expiration_str)?.with_timezone(&Utc);
-
-    Ok((token, expiration))
+/// Formats a timestamp the way SPIC expects dates in request bodies.
+fn format_ms_date(date: &DateTime<Utc>) -> String {
+    format!("/Date({})/", date.timestamp_millis())
 }
-fn deserialize_ms_date<'de, D>(date: D) -> Result<DateTime<Utc>, D::Error>
+
+fn deserialize_ms_date<'de, D>(date: D) -> Result<DateTime<FixedOffset>, D::Error>
 where
     D: Deserializer<'de>,
 {
     let date_str: String = String::deserialize(date)?;
 
-    let ms = date_str
-        .trim_start_matches("/Date(")
-        .trim_end_matches(")/")
-        .split(|c| c == '+' || c == '-')
-        .next()
-        .unwrap()
-        .parse::<i64>()
-        .expect("Failed to parse date");
-
-    Ok(Utc.timestamp_millis_opt(ms).unwrap())
+    parse_ms_date(&date_str).map_err(serde::de::Error::custom)
 }
 
 // TODO: define  a custom json parser for response from server
 
-macro_rules! endpoint {
-    ($ep:ident) => {
-        SpicEndpoint::$ep.0
-    };
-}
-
-#[derive(Debug)]
+/// Handle to a SPIC account. Clones are cheap and share the session and the
+/// subscriptions, so pollers on different tasks can each hold their own.
+#[derive(Debug, Clone)]
 pub struct SpicClient {
     client: Client,
-    auth_token: Option<AuthToken>,
-    subman: Arc<Mutex<SubscriptionManager>>,
-    config: Option<SpicConfig>,
-}
-
-// Add retry mechanism for network requests
-async fn with_retry<F, T, E>(f: F, max_retries: u32) -> Result<T, E>
-where
-    F: Fn() -> dyn Future<Output = Result<T, E>>,
-{
-    //TODO: Implement exponential backoff retry logic
-    todo!();
+    session: Arc<Session<AuthToken>>,
+    subman: Arc<RwLock<SubscriptionManager>>,
+    limits: Arc<RateLimits>,
+    token_store: Arc<dyn TokenStore>,
+    transport: Arc<Transport>,
+    config: Arc<SpicConfig>,
+    password: Secret,
+    timezone: Tz,
+    endpoints: Arc<SpicEndpoints>,
+    retry: RetryPolicy,
+    subscription_timeout: std::time::Duration,
+    subscription_poll_interval: std::time::Duration,
+    online_data_chunk_size: usize,
+    token_refresh_margin: Duration,
+    history_window: Duration,
+    history_page_size: usize,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -98,40 +81,21 @@ struct Uuid {
 }
 
 impl Uuid {
-    fn from_subscription_response(json_data: &String) -> Result<Uuid, SpicError> {
-        match serde_json::from_str::<Uuid>(&json_data) {
-            Ok(data) => Ok(data),
-            Err(e) => Err(SpicError::JsonError {
-                caller: "Uuid::from_subscription_response",
-                source: e,
-                data: json_data.to_string(),
-            }),
-        }
-    }
-
-    fn from_string(uuid: String) -> Self {
-        Uuid { uuid }
-    }
-
-    fn as_str(&self) -> &str {
-        &self.uuid
-    }
-
     fn as_string(&self) -> String {
         self.uuid.clone()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct AuthToken {
-    token: Uuid,
+    token: Secret,
     expiration: DateTime<Utc>,
 }
 
 impl AuthToken {
-    fn new(token: String, date: DateTime<Utc>) -> Self {
+    fn new(token: Secret, date: DateTime<Utc>) -> Self {
         AuthToken {
-            token: Uuid::from_string(token),
+            token,
             expiration: date,
         }
     }
@@ -140,186 +104,630 @@ impl AuthToken {
         self.expiration < Utc::now()
     }
 
-    fn is_valid(&self) -> bool {
-        // TODO: Check for validity of token, try to use some API request
-        // to check if token is valid
+    /// Probes the session with a cheap authorized request, SPIC answers with
+    /// the number of units only while the session is alive.
+    async fn is_valid(&self, client: &Client, transport: &Transport, endpoints: &SpicEndpoints) -> bool {
+        if self.is_expired() {
+            return false;
+        }
 
-        true
+        let request = client
+            .get(&endpoints.units_number_service)
+            .header("ScoutAuthorization", self.token.expose());
+        let response = transport.send(client, request).await;
+
+        match response {
+            Ok(response) if response.status() == reqwest::StatusCode::OK => response
+                .text()
+                .await
+                .is_ok_and(|body| body.trim().parse::<i32>().is_ok()),
+            _ => false,
+        }
     }
 }
 
 impl SpicClient{
-    fn new(client: Client) -> Self {
-
-        let config:SpicConfig = conf!(spic);
+    /// Request with the JSON headers and, once logged in, the `ScoutAuthorization` header.
+    fn request(&self, method: reqwest::Method, url: &str) -> reqwest::RequestBuilder {
+        let request = self
+            .client
+            .request(method, url)
+            .header(header::ACCEPT, "application/json; charset=utf-8");
 
-        SpicClient {
-            client,
-            auth_token: None,
-            subman: Arc::new(Mutex::new(SubscriptionManager::new())),
-            config: Some(config),
+        match self.session.token() {
+            Some(auth_token) => request.header("ScoutAuthorization", auth_token.token.expose()),
+            None => request,
         }
     }
 
-    fn authenticated_client(&self, token: &str) -> Client {
-        let mut headers = header::HeaderMap::new();
-        headers.insert(
-            "ScoutAuthorization",
-            header::HeaderValue::from_str(token).unwrap(),
-        );
+    /// Sends the request live, recording it or from fixtures, see [`Transport`].
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, SpicError> {
+        Ok(self.transport.send(&self.client, request).await?)
+    }
 
-        headers.insert(
-            "Content-Type",
-            header::HeaderValue::from_static("application/json"),
-        );
+    /// Time left until the session should be refreshed, `None` without a session.
+    fn refresh_due_in(&self) -> Option<std::time::Duration> {
+        let auth_token = self.session.token()?;
+        let refresh_at = auth_token.expiration - self.token_refresh_margin;
 
-        headers.insert(
-            "Accept",
-            header::HeaderValue::from_static("application/json; charset=utf-8"),
-        );
-        Client::builder()
-            .user_agent(DEFAULT_USER_AGENT)
-            .default_headers(headers)
-            .build()
-            .expect("Unable to create authenticated reqwest client")
+        Some((refresh_at - Utc::now()).to_std().unwrap_or(std::time::Duration::ZERO))
+    }
+
+    /// Logs in again `token_refresh_margin_s` before the session expires, so
+    /// polling never runs into an expired session. Abort the handle to stop it.
+    pub fn spawn_token_refresh(&self) -> tokio::task::JoinHandle<()> {
+        let client = self.clone();
+        let check_interval = std::time::Duration::from_secs(TOKEN_REFRESH_CHECK_INTERVAL_SECONDS);
+
+        tokio::spawn(async move {
+            loop {
+                match client.refresh_due_in() {
+                    Some(due) if due.is_zero() => {
+                        let generation = client.session.generation();
+                        match client.relogin(generation, false).await {
+                            Ok(()) => println!("Session refreshed before expiration"),
+                            Err(e) => println!("Session refresh failed: {}", e),
+                        }
+
+                        if client.refresh_due_in().is_some_and(|due| due.is_zero()) {
+                            tokio::time::sleep(check_interval).await;
+                        }
+                    }
+                    Some(due) => tokio::time::sleep(due.min(check_interval)).await,
+                    None => tokio::time::sleep(check_interval).await,
+                }
+            }
+        })
     }
 
-    pub async fn authenticate(&mut self) -> Result<bool, SpicError> {
+    /// Converts a timestamp into the zone configured in `[spic]` `timezone`
+    /// for showing it to users.
+    pub fn local_time<T: TimeZone>(&self, date: &DateTime<T>) -> DateTime<Tz> {
+        date.with_timezone(&self.timezone)
+    }
 
-        let _config = self.config.as_ref().unwrap();
+    /// How long requests waited for the rate and in-flight limits so far,
+    /// per endpoint class.
+    pub fn rate_limit_stats(&self) -> Vec<(EndpointClass, WaitStats)> {
+        self.limits.wait_stats()
+    }
 
-        let (login, password) = (_config.login.as_str(), _config.password.as_str());
+    /// Restores the session cached in the token store if SPIC still accepts
+    /// it, logs in otherwise.
+    pub async fn authenticate(&self) -> Result<(), SpicError> {
+        let _login = self.session.lock_login().await;
 
+        let stored = self.token_store.load(&self.config.login).unwrap_or_else(|e| {
+            println!("Failed to read stored auth data: {}", e);
+            None
+        });
 
-        if let Ok((token, expiration)) = get_stored_auth_data() {
+        if let Some(StoredToken { token, expiration }) = stored {
             println!(
-                "Stored auth data found, \n Token: {}\n Expiration: {}",
-                token, expiration
+                "Stored auth data found, expiration: {}",
+                self.local_time(&expiration)
             );
-            self.auth_token = Some(AuthToken::new(token, expiration));
+            let auth_token = AuthToken::new(token, expiration);
 
-            if !self.auth_token.as_ref().unwrap().is_expired() {
-                println!("Token is not expired");
-                self.client =
-                    self.authenticated_client(&self.auth_token.as_ref().unwrap().token.as_string());
-                return Ok(true);
+            let is_valid = {
+                let _slot = self.limits.acquire(EndpointClass::Auth).await;
+                auth_token.is_valid(&self.client, &self.transport, &self.endpoints).await
+            };
+
+            if is_valid {
+                println!("Stored token is valid");
+                self.session.set(auth_token);
+                return Ok(());
             }
+
+            println!("Stored token is expired or revoked");
         }
 
+        self.login().await
+    }
+
+    /// Callers hold the login lock of the session.
+    async fn login(&self) -> Result<(), SpicError> {
+        let _config = self.config.as_ref();
+
+        let (login, password) = (_config.login.as_str(), self.password.expose());
 
-        // TODO: get credentials from config
         let json_data = json!({
             "Login": login,
             "Password": password,
-            "TimeZoneOlsonId": "Asia/Yekaterinburg",
-            "CultureName": "ru-ru",
-            "UiCultureName": "ru-ru"
+            "TimeZoneOlsonId": _config.timezone.name(),
+            "CultureName": _config.culture,
+            "UiCultureName": _config.ui_culture
         });
 
-        let response = self
-            .client
-            .post(endpoint!(AUTHORIZATION_SERVICE))
-            .json(&json_data)
-            .send()
-            .await?;
+        let auth_response = with_retry(&self.retry, "SpicClient::login", || {
+            self.request_login(&json_data)
+        })
+        .await?;
+
+        if auth_response.is_authorized && auth_response.is_authenticated {
+            let expiration = auth_response.expire_date.with_timezone(&Utc);
+            let stored = StoredToken {
+                token: auth_response.session_id.clone(),
+                expiration,
+            };
+            if let Err(e) = self.token_store.store(&_config.login, &stored) {
+                println!("Failed to store auth data: {}", e);
+            }
 
-        match response.status() {
-            reqwest::StatusCode::OK => {
-                let body = response.text().await?;
+            println!(
+                "Authentication successful, user id: {}, expires at: {}",
+                auth_response.user_id,
+                self.local_time(&expiration)
+            );
 
-                println!("Requesting authentication token...");
+            self.session.set(AuthToken::new(auth_response.session_id, expiration));
 
-                let auth_response = AuthResponse::from_json(&body)?;
+            Ok(())
+        } else {
+            Err(SpicError::AuthenticationError(
+                "Authentication failed".to_string(),
+            ))
+        }
+    }
+
+    pub fn logout_on_exit(&self) -> bool {
+        self.config.logout_on_exit
+    }
+
+    /// Logs in again unless another caller already did since it saw
+    /// `seen_generation`. Subscriptions belong to the old session, so they are
+    /// dropped with it when `drop_subscriptions` is set.
+    async fn relogin(&self, seen_generation: u64, drop_subscriptions: bool) -> Result<(), SpicError> {
+        self.session
+            .relogin(seen_generation, || async {
+                if drop_subscriptions {
+                    self.subman.write().unwrap().subscriptions.clear();
+                }
+
+                self.login().await
+            })
+            .await
+    }
+
+    /// Runs an authorized request, see [`Session::authorized`].
+    async fn authorized<F, Fut, T>(&self, caller: &'static str, request: F) -> Result<T, SpicError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, SpicError>>,
+    {
+        self.session
+            .authorized(&self.retry, caller, request, |generation| self.relogin(generation, true))
+            .await
+    }
+
+    /// Name from the config the data of this client is stored under.
+    pub fn account(&self) -> &str {
+        &self.config.account
+    }
+
+    /// True while SPIC is considered down and requests fail fast.
+    pub fn is_circuit_open(&self) -> bool {
+        self.session.is_unavailable()
+    }
+
+    /// Ends the SPIC session and removes the cached session from the token store.
+    /// Local state is reset even if the server could not be reached.
+    pub async fn logout(&self) -> Result<(), SpicError> {
+        let _slot = self.limits.acquire(EndpointClass::Auth).await;
 
-                if auth_response.is_authorized && auth_response.is_authenticated {
-                    let _ = store_auth_data(&auth_response.session_id,
-                         &{auth_response.expire_date - Duration::hours(LOCAL_TIME_SHIFT)});
+        let is_logged_in = self.session.token().is_some();
+        let response = if is_logged_in {
+            Some(self.send(self.request(reqwest::Method::GET, &self.endpoints.authorization_logout)).await)
+        } else {
+            None
+        };
 
-                    println!(
-                        "Authentication successful, user id: {}, session id: {}",
-                        auth_response.user_id, auth_response.session_id
-                    );
+        if let Err(e) = self.token_store.clear(&self.config.login) {
+            println!("Failed to clear stored auth data: {}", e);
+        }
 
-                    self.client = self.authenticated_client(&auth_response.session_id);
+        self.session.take();
+        self.subman.write().unwrap().subscriptions.clear();
 
-                    Ok(true)
+        match response {
+            Some(response) => {
+                let response = response?;
+                if response.status() == reqwest::StatusCode::OK {
+                    Ok(())
                 } else {
-                    Err(SpicError::AuthenticationError(
-                        "Authentication failed".to_string(),
-                    ))
+                    Err(http_status_error(&self.endpoints.authorization_logout, response).await)
                 }
             }
-            _ => {
-                // TODO: handle error and add logging instead
-                println!(
-                    "Authentication failed with status code: {}",
-                    response.status()
-                );
-                return Ok(false);
+            None => Ok(()),
+        }
+    }
+
+    async fn request_login(&self, json_data: &serde_json::Value) -> Result<AuthResponse, SpicError> {
+        let _slot = self.limits.acquire(EndpointClass::Auth).await;
+
+        let request = self
+            .request(reqwest::Method::POST, &self.endpoints.authorization_service)
+            .json(json_data);
+
+        let response = self.send(request).await?;
+
+        match response.status() {
+            reqwest::StatusCode::OK => {
+                let body = response.text().await?;
+
+                println!("Requesting authentication token...");
+
+                AuthResponse::from_json(&body)
             }
+            _ => Err(http_status_error(&self.endpoints.authorization_service, response).await),
         }
     }
 
     pub async fn number_of_units(&self) -> Result<i32, SpicError> {
-        let response = self
-            .client
-            .get(endpoint!(UNITS_NUMBER_SERVICE))
-            .send()
-            .await?;
+        self.authorized("SpicClient::number_of_units", || {
+            self.request_number_of_units()
+        })
+        .await
+    }
+
+    async fn request_number_of_units(&self) -> Result<i32, SpicError> {
+        let _slot = self.limits.acquire(EndpointClass::Units).await;
+
+        let response = self.send(self.request(reqwest::Method::GET, &self.endpoints.units_number_service)).await?;
 
         match response.status() {
-            // TODO: rewrite error handling using spicerror
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+                Err(SpicError::Unauthorized {
+                    caller: "SpicClient::number_of_units",
+                })
+            }
             reqwest::StatusCode::OK => {
                 let body = response.text().await?;
-                let number_of_units = body.parse::<i32>()?;
+                let number_of_units = body.trim().parse::<i32>()?;
                 Ok(number_of_units)
             }
-            _ => {
-                // TODO: handle error and add logging instead
-                println!(
-                    "failed to get number of units with status code: {}",
-                    response.status()
-                );
-                return Ok(0);
-            }
+            _ => Err(http_status_error(&self.endpoints.units_number_service, response).await),
         }
     }
 
     pub async fn unit_list(&self) -> Result<Vec<SpicUnit>, SpicError> {
-        let response = self.client.get(endpoint!(UNIT_LIST_SERVICE)).send().await?;
+        self.authorized("SpicClient::unit_list", || self.request_unit_list()).await
+    }
+
+    async fn request_unit_list(&self) -> Result<Vec<SpicUnit>, SpicError> {
+        let _slot = self.limits.acquire(EndpointClass::Units).await;
+
+        let response = self.send(self.request(reqwest::Method::GET, &self.endpoints.unit_list_service)).await?;
 
         match response.status() {
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+                Err(SpicError::Unauthorized {
+                    caller: "SpicClient::unit_list",
+                })
+            }
             reqwest::StatusCode::OK => {
                 let body = response.text().await?;
                 let unit_list = SpicUnitList::from_json(&body)?;
                 Ok(unit_list.units)
             }
-            _ => {
-                // TODO: handle error and add logging instead
-                println!(
-                    "failed to get unit list with status code: {}",
-                    response.status()
-                );
-                return Ok(vec![]);
+            _ => Err(http_status_error(&self.endpoints.unit_list_service, response).await),
+        }
+    }
+
+    /// Unit groups of the account with their members.
+    ///
+    /// Unverified: the `GetAllUnitGroups` path and response shape are not
+    /// checked against a recorded SPIC response yet, `[spic.endpoints]
+    /// unit_groups` overrides the path.
+    pub async fn unit_groups(&self) -> Result<Vec<SpicUnitGroup>, SpicError> {
+        self.authorized("SpicClient::unit_groups", || self.request_unit_groups()).await
+    }
+
+    async fn request_unit_groups(&self) -> Result<Vec<SpicUnitGroup>, SpicError> {
+        let _slot = self.limits.acquire(EndpointClass::Units).await;
+
+        let response = self.send(self.request(reqwest::Method::GET, &self.endpoints.unit_group_service)).await?;
+
+        match response.status() {
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+                Err(SpicError::Unauthorized {
+                    caller: "SpicClient::unit_groups",
+                })
+            }
+            reqwest::StatusCode::OK => {
+                let body = response.text().await?;
+                let group_list = SpicUnitGroupList::from_json(&body)?;
+                Ok(group_list.groups)
+            }
+            _ => Err(http_status_error(&self.endpoints.unit_group_service, response).await),
+        }
+    }
+
+    /// Stored messages of a unit between `from` and `to`, ordered by time.
+    /// The range is requested in windows of `history_window_hours`, each window
+    /// is paged by `history_page_size` messages.
+    ///
+    /// Unverified: the `Skip`/`Take` paging of GetMessages is not checked
+    /// against a recorded SPIC response yet. Paging stops as soon as a page is
+    /// larger than requested or does not move past the previous one, so a
+    /// server ignoring these fields ends up with one page per window.
+    pub async fn track_history(&self, unit_id: i32, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<TrackPoint>, SpicError> {
+        let mut points = Vec::new();
+        let mut window_start = from;
+
+        while window_start < to {
+            let window_end = (window_start + self.history_window).min(to);
+            let mut skip = 0;
+            let mut last_time = None;
+
+            loop {
+                let messages = self
+                    .authorized("SpicClient::track_history", || {
+                        self.request_history(unit_id, window_start, window_end, skip)
+                    })
+                    .await?;
+
+                let received = messages.len();
+                let page_last_time = messages.iter().map(|message| message.time).max();
+                points.extend(messages.into_iter().filter_map(|message| {
+                    message.navigation.map(|navigation| TrackPoint {
+                        unit_id,
+                        time: message.time,
+                        navigation,
+                    })
+                }));
+
+                if received != self.history_page_size || page_last_time <= last_time {
+                    break;
+                }
+                last_time = page_last_time;
+                skip += received;
+            }
+
+            window_start = window_end;
+        }
+
+        points.sort_by_key(|point| point.time);
+        points.dedup_by_key(|point| point.time);
+
+        Ok(points)
+    }
+
+    async fn request_history(
+        &self,
+        unit_id: i32,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        skip: usize,
+    ) -> Result<Vec<HistoryMessage>, SpicError> {
+        let _slot = self.limits.acquire(EndpointClass::Units).await;
+
+        let json_request = json!({
+            "UnitId": unit_id,
+            "From": format_ms_date(&from),
+            "To": format_ms_date(&to),
+            "Skip": skip,
+            "Take": self.history_page_size,
+        });
+
+        let request = self
+            .request(reqwest::Method::POST, &self.endpoints.track_history_service)
+            .json(&json_request);
+
+        let response = self.send(request).await?;
+
+        match response.status() {
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+                Err(SpicError::Unauthorized {
+                    caller: "SpicClient::track_history",
+                })
+            }
+            reqwest::StatusCode::OK => {
+                let body = response.text().await?;
+                let history = HistoryResponse::from_json(&body)?;
+                Ok(history.messages)
             }
+            _ => Err(http_status_error(&self.endpoints.track_history_service, response).await),
         }
     }
 
+    /// Online data of one unit, see [`SpicClient::get_online_data_many`].
     pub async fn get_online_data(&self, unit_id: i32) -> Result<OnlineData, SpicError> {
-        let _subman = self.subman.clone();
+        let mut batch = self.get_online_data_many(&[unit_id]).await?;
+
+        batch.data.remove(&unit_id).ok_or_else(|| SpicError::NoOnlineData {
+            unit_id,
+            reason: batch.missing.remove(&unit_id).unwrap_or(MissingReason::Timeout),
+        })
+    }
+
+    /// Fetches online data for many units. Live subscriptions are reused, the
+    /// rest of the units get one new subscription per chunk of
+    /// `online_data_chunk_size` units. Units SPIC returned nothing for are
+    /// listed in `OnlineDataBatch::missing` with the reason, so are the units
+    /// of a chunk SPIC answered with an error. Only connection and session
+    /// failures fail the whole batch.
+    pub async fn get_online_data_many(&self, unit_ids: &[i32]) -> Result<OnlineDataBatch, SpicError> {
+        let mut batch = OnlineDataBatch::with_capacity(unit_ids.len());
+
+        let (live, mut unsubscribed) = self.subman.read().unwrap().group_by_live_subscription(unit_ids);
+
+        for (subscription_id, subscribed) in live {
+            match self.wait_for_reused(&subscribed, &subscription_id).await {
+                Ok(Some(subscription_batch)) => batch.merge(subscription_batch),
+                Ok(None) => unsubscribed.extend(subscribed),
+                Err(e) => batch.fail(&subscribed, e)?,
+            }
+        }
+
+        for chunk in unsubscribed.chunks(self.online_data_chunk_size) {
+            match self.subscribe_and_wait(chunk).await {
+                Ok(chunk_batch) => batch.merge(chunk_batch),
+                Err(e) => batch.fail(chunk, e)?,
+            }
+        }
+
+        if !batch.missing.is_empty() {
+            println!(
+                "No online data for {} of {} units: {:?}",
+                batch.missing.len(),
+                unit_ids.len(),
+                batch.missing
+            );
+        }
+
+        Ok(batch)
+    }
+
+    /// Subscribes the units and waits for their data. SPIC may lose a fresh
+    /// subscription, `SubscriptionNotFound` is answered with one resubscribe.
+    async fn subscribe_and_wait(&self, unit_ids: &[i32]) -> Result<OnlineDataBatch, SpicError> {
+        let mut resubscribed = false;
+
+        loop {
+            let subscription_id = self.authorized("SpicClient::subscribe", || {
+                self.subscribe(unit_ids)
+            })
+            .await?;
+
+            match self.wait_for_online_data(unit_ids, &subscription_id).await {
+                Err(SpicError::SubscriptionNotFound { .. }) if !resubscribed => {
+                    println!("Subscription {} was lost, resubscribing", subscription_id);
+                    resubscribed = true;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Polls a subscription taken from the manager. If SPIC does not know it
+    /// anymore the subscription is dropped and `None` is returned, so the caller
+    /// can subscribe again.
+    async fn wait_for_reused(&self, unit_ids: &[i32], subscription_id: &str) -> Result<Option<OnlineDataBatch>, SpicError> {
+        match self.wait_for_online_data(unit_ids, subscription_id).await {
+            Ok(batch) => Ok(Some(batch)),
+            Err(SpicError::SubscriptionNotFound { .. }) => {
+                println!("Subscription {} was not found, resubscribing", subscription_id);
+                let mut subman = self.subman.write().unwrap();
+                for unit_id in unit_ids {
+                    subman.remove_subscription(*unit_id);
+                }
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Keeps the subscriptions alive for as long as the client is used: every
+    /// `SUBSCRIPTION_RENEWAL_INTERVAL_SECONDS` expired subscriptions are dropped
+    /// and the units of subscriptions about to expire are subscribed again.
+    /// Abort the handle to stop it.
+    pub fn spawn_subscription_renewal(&self) -> tokio::task::JoinHandle<()> {
+        let client = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+                SUBSCRIPTION_RENEWAL_INTERVAL_SECONDS,
+            ));
+
+            loop {
+                interval.tick().await;
+                client.renew_subscriptions().await;
+            }
+        })
+    }
+
+    async fn renew_subscriptions(&self) {
+        let due = {
+            let mut subman = self.subman.write().unwrap();
+            subman.clear_expired();
+            subman.due_for_renewal()
+        };
+
+        for chunk in due.chunks(self.online_data_chunk_size) {
+            let renewed = self.authorized("SpicClient::renew_subscriptions", || self.subscribe(chunk)).await;
+            match renewed {
+                Ok(subscription_id) => println!("Subscription {} renewed for {} units", subscription_id, chunk.len()),
+                Err(e) => println!("Renewing the subscription of units {:?} failed: {}", chunk, e),
+            }
+        }
+    }
+
+    /// Subscriptions are not ready right away: until SPIC has collected the data
+    /// `GetOnlineData` answers with `Busy`/`PartialOk` or an empty collection.
+    /// Polls until every unit has data or a unit error code, or the readiness
+    /// deadline passes.
+    async fn wait_for_online_data(&self, unit_ids: &[i32], subscription_id: &str) -> Result<OnlineDataBatch, SpicError> {
+        let started = Instant::now();
+        let deadline = started + self.subscription_timeout;
+        let mut data = HashMap::with_capacity(unit_ids.len());
+        let mut failed = HashMap::new();
+
+        loop {
+            let received = self.authorized("SpicClient::wait_for_online_data", || {
+                self.request_online_data(subscription_id)
+            })
+            .await?;
+
+            data.extend(received.data.into_iter().filter(|(id, _)| unit_ids.contains(id)));
+            failed.extend(
+                received
+                    .failed
+                    .into_iter()
+                    .filter(|(id, _)| unit_ids.contains(id) && !data.contains_key(id)),
+            );
+
+            if unit_ids.iter().all(|id| data.contains_key(id) || failed.contains_key(id)) {
+                break;
+            }
+
+            if Instant::now() + self.subscription_poll_interval > deadline {
+                if data.is_empty() && failed.is_empty() {
+                    return Err(SpicError::SubscriptionTimeout {
+                        unit_ids: unit_ids.to_vec(),
+                        subscription_id: subscription_id.to_string(),
+                        waited_ms: started.elapsed().as_millis(),
+                    });
+                }
+                break;
+            }
+
+            tokio::time::sleep(self.subscription_poll_interval).await;
+        }
+
+        let missing = unit_ids
+            .iter()
+            .filter(|id| !data.contains_key(id))
+            .map(|id| {
+                let reason = failed.get(id).map_or(MissingReason::Timeout, |code| MissingReason::Code(*code));
+                (*id, reason)
+            })
+            .collect();
+
+        Ok(OnlineDataBatch { data, missing })
+    }
+
+    /// Subscribes to online data of the units and returns the subscription id.
+    async fn subscribe(&self, unit_ids: &[i32]) -> Result<String, SpicError> {
+        let _slot = self.limits.acquire(EndpointClass::OnlineData).await;
 
         let json_request = json!({
-            "UnitIds" : [unit_id]
+            "UnitIds" : unit_ids
         });
 
         let req = self
-            .client
-            .post(endpoint!(ONLINE_DATA_SUBSCRIBE))
+            .request(reqwest::Method::POST, &self.endpoints.online_data_subscribe)
             .json(&json_request);
 
-        let response = req.send().await?;
+        let response = self.send(req).await?;
 
         match response.status() {
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+                Err(SpicError::Unauthorized {
+                    caller: "SpicClient::subscribe",
+                })
+            }
             reqwest::StatusCode::OK => {
                 let body = response.text().await?;
                 let data = serde_json::from_str::<SubscriptionResponse>(&body);
@@ -327,94 +735,82 @@ impl SpicClient{
                 match data {
                     Ok(data) => {
                         if data.state.is_ok() {
-                            let subscription_token = data.session_id;
-                            _subman
-                                .lock()
-                                .unwrap()
-                                .add_subscription(unit_id, subscription_token.as_string());
+                            let subscription_token = data.session_id.as_string();
+                            let mut subman = self.subman.write().unwrap();
+                            for unit_id in unit_ids {
+                                subman.add_subscription(*unit_id, subscription_token.clone());
+                            }
+                            Ok(subscription_token)
+                        } else if data.state.is_busy() {
+                            Err(SpicError::ServiceBusy {
+                                caller: "SpicClient::subscribe",
+                            })
                         } else {
-                            println!("failed to subscribe to unit with id: {}", unit_id);
-                            return Err(SpicError::SubscriptionError {
-                                caller: "SpicClient::get_online_data",
-                                data: format!("failed to subscribe to unit with id: {}", unit_id),
-                                source: std::io::Error::new(
-                                    std::io::ErrorKind::Other,
-                                    "failed to subscribe to unit",
-                                ),
-                            });
+                            let codes = data.state.error_codes();
+                            println!("failed to subscribe to units with ids: {:?}, codes: {:?}", unit_ids, codes);
+                            Err(SpicError::from_codes("SpicClient::subscribe", &codes, unit_ids))
                         }
                     }
-                    Err(e) => {
-                        return Err(SpicError::JsonError {
-                            caller: "SpicClient::get_online_data",
-                            source: e,
-                            data: body.to_string(),
-                        })
-                    }
+                    Err(e) => Err(SpicError::JsonError {
+                        caller: "SpicClient::subscribe",
+                        source: e,
+                        data: body.to_string(),
+                    }),
                 }
             }
-            _ => {
-                return Err(SpicError::NetworkError(
-                    response.error_for_status().unwrap_err(),
-                ));
-            }
+            _ => Err(http_status_error(&self.endpoints.online_data_subscribe, response).await),
         }
+    }
+
+    /// Returns whatever data the subscription has collected so far and the
+    /// units SPIC reported an error code for. Both are empty while SPIC is
+    /// still busy collecting.
+    async fn request_online_data(&self, subscription_id: &str) -> Result<ODReceived, SpicError> {
+        let _slot = self.limits.acquire(EndpointClass::OnlineData).await;
 
         let subscribed_json = json!({
-            "Id": _subman.lock().unwrap().subscriptions.get(&unit_id).unwrap().uuid,
+            "Id": subscription_id,
         });
 
-        let response = self
-            .client
-            .post(endpoint!(ONLINE_DATA_GET))
-            .json(&subscribed_json)
-            .send()
-            .await?;
+        let request = self
+            .request(reqwest::Method::POST, &self.endpoints.online_data_get)
+            .json(&subscribed_json);
+
+        let response = self.send(request).await?;
 
         match response.status() {
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+                Err(SpicError::Unauthorized {
+                    caller: "SpicClient::request_online_data",
+                })
+            }
             reqwest::StatusCode::OK => {
                 let body = response.text().await?;
                 let online_data_col = serde_json::from_str::<ODResponse>(&body);
 
                 match online_data_col {
                     Ok(data) => {
-                        if data.is_ok() {
-                            let online_data = data
-                                .online_data_collection
-                                .data_collection
-                                .unwrap()
-                                .first()
-                                .unwrap()
-                                .clone();
-                            return Ok(online_data);
+                        if data.state.is_busy() {
+                            Ok(ODReceived::default())
+                        } else if data.is_ok() || data.state.is_partial() || data.has_only_unit_errors() {
+                            Ok(data.into_received())
                         } else {
-                            return Err(SpicError::ResponseError {
-                                caller: "SpicClient::get_online_data",
-                                data: format!(
-                                    "failed to get online data for unit with id: {}",
-                                    unit_id
-                                ),
-                                source: std::io::Error::new(
-                                    std::io::ErrorKind::Other,
-                                    "failed to get online data",
-                                ),
-                            });
+                            let codes = data.state.error_codes();
+                            Err(SpicError::from_codes(
+                                "SpicClient::request_online_data",
+                                &codes,
+                                &data.online_data_collection.targets,
+                            ))
                         }
                     }
-                    Err(e) => {
-                        return Err(SpicError::JsonError {
-                            caller: "SpicClient::get_online_data",
-                            source: e,
-                            data: body.to_string(),
-                        })
-                    }
+                    Err(e) => Err(SpicError::JsonError {
+                        caller: "SpicClient::request_online_data",
+                        source: e,
+                        data: body.to_string(),
+                    }),
                 }
             }
-            _ => {
-                return Err(SpicError::NetworkError(
-                    response.error_for_status().unwrap_err(),
-                ));
-            }
+            _ => Err(http_status_error(&self.endpoints.online_data_get, response).await),
         }
     }
 }
@@ -441,57 +837,210 @@ pub enum SpicError {
     #[error("Authentication error: {0}")]
     AuthenticationError(String),
 
-    #[error("Subscription error: {source} \n in {caller}")]
-    SubscriptionError {
+    #[error("SPIC rejected the request as malformed \n in {caller}")]
+    BadRequest { caller: &'static str },
+
+    /// `unit_id` is set when the request was about this unit alone.
+    #[error("No rights for unit {unit_id:?} \n in {caller}")]
+    RightsViolation {
         caller: &'static str,
-        data: String,
-        #[source]
-        source: std::io::Error,
+        unit_id: Option<i32>,
     },
 
-    #[error("Response error: {data} \n in {caller}")]
-    ResponseError {
+    #[error("SPIC internal error \n in {caller}")]
+    InternalError { caller: &'static str },
+
+    #[error("Terminal of unit {unit_id:?} not found \n in {caller}")]
+    TerminalNotFound {
         caller: &'static str,
-        data: String,
-        #[source]
-        source: std::io::Error,
+        unit_id: Option<i32>,
     },
-}
 
-#[derive(Debug)]
-struct Url(&'static str);
+    #[error("Subscription not found \n in {caller}")]
+    SubscriptionNotFound { caller: &'static str },
 
-impl Url {
-    fn new(url: &'static str) -> Self {
-        Url(url)
-    }
+    #[error("No online data for unit {unit_id:?} \n in {caller}")]
+    OnlineDataNotFound {
+        caller: &'static str,
+        unit_id: Option<i32>,
+    },
+
+    /// Error status with codes this client does not know, if any.
+    #[error("SPIC error codes {codes:?} \n in {caller}")]
+    UnknownError {
+        caller: &'static str,
+        codes: Vec<i32>,
+    },
+
+    #[error("HTTP status {status} from {endpoint}: {body}")]
+    HttpStatus {
+        endpoint: String,
+        status: reqwest::StatusCode,
+        body: String,
+    },
+
+    #[error("Session is not authorized \n in {caller}")]
+    Unauthorized { caller: &'static str },
+
+    #[error("SPIC service is busy \n in {caller}")]
+    ServiceBusy { caller: &'static str },
+
+    #[error("Subscription {subscription_id} for units {unit_ids:?} was not ready after {waited_ms} ms")]
+    SubscriptionTimeout {
+        unit_ids: Vec<i32>,
+        subscription_id: String,
+        waited_ms: u128,
+    },
+
+    #[error("No online data returned for unit {unit_id}: {reason:?}")]
+    NoOnlineData { unit_id: i32, reason: MissingReason },
+
+    #[error("Fixture error: {0}")]
+    Fixture(TransportError),
+
+    #[error("Unable to get the SPIC password: {0}")]
+    Password(#[from] SecretError),
+
+    #[error("Token store error: {0}")]
+    TokenStore(#[from] TokenStoreError),
+
+    #[error("SPIC is considered down, {caller} not sent, next probe in {retry_in_ms} ms")]
+    CircuitOpen {
+        caller: &'static str,
+        retry_in_ms: u128,
+    },
 }
 
-macro_rules! spic_url {
-    ($path:expr) => {
-        Url(concat!("http://login.scout-gps.ru/spic", $path))
-    };
+impl From<TransportError> for SpicError {
+    fn from(e: TransportError) -> Self {
+        match e {
+            TransportError::Network(e) => SpicError::NetworkError(e),
+            e => SpicError::Fixture(e),
+        }
+    }
 }
 
-#[derive(Debug)]
-struct SpicEndpoint;
+impl SpicError {
+    /// One error for the codes of a subscribe or get online data answer.
+    /// Request level codes take precedence over unit codes. SPIC does not say
+    /// which unit a code belongs to, so `unit_id` is only set when `unit_ids`
+    /// holds a single unit.
+    fn from_codes(caller: &'static str, codes: &[ODErrorCodes], unit_ids: &[i32]) -> SpicError {
+        let unit_id = match unit_ids {
+            [unit_id] => Some(*unit_id),
+            _ => None,
+        };
+
+        if codes.contains(&ODErrorCodes::SubscriptionNotFound) {
+            SpicError::SubscriptionNotFound { caller }
+        } else if codes.contains(&ODErrorCodes::InternalError) {
+            SpicError::InternalError { caller }
+        } else if codes.contains(&ODErrorCodes::BadRequest) {
+            SpicError::BadRequest { caller }
+        } else if codes.contains(&ODErrorCodes::RightsViolation) {
+            SpicError::RightsViolation { caller, unit_id }
+        } else if codes.contains(&ODErrorCodes::TerminalNotFound) {
+            SpicError::TerminalNotFound { caller, unit_id }
+        } else if codes.contains(&ODErrorCodes::OnlineDataNotFound) {
+            SpicError::OnlineDataNotFound { caller, unit_id }
+        } else {
+            let codes = codes
+                .iter()
+                .filter_map(|code| match code {
+                    ODErrorCodes::Unknown(code) => Some(*code),
+                    _ => None,
+                })
+                .collect();
+            SpicError::UnknownError { caller, codes }
+        }
+    }
 
-#[allow(unused)]
-impl SpicEndpoint {
-    const AUTHORIZATION_SERVICE: Url = spic_url!("/auth/rest/login");
-    const AUTHORIZATION_LOGOUT: Url = spic_url!("/auth/rest/logout");
-    const UNITS_NUMBER_SERVICE: Url = spic_url!("/Units/rest/");
-    const UNIT_LIST_SERVICE: Url = spic_url!("/Units/rest/GetAllUnits");
-    const UNIT_GROUP_SERVICE: Url = spic_url!("/UnitGroups");
-    const ONLINE_DATA_SUBSCRIBE: Url = spic_url!("/OnlineDataService/rest/Subscribe");
-    const ONLINE_DATA_GET: Url = spic_url!("/OnlineDataService/rest/GetOnlineData");
+    /// The `OnlineDataService` error code this error stands for.
+    pub fn error_code(&self) -> Option<ODErrorCodes> {
+        match self {
+            SpicError::BadRequest { .. } => Some(ODErrorCodes::BadRequest),
+            SpicError::RightsViolation { .. } => Some(ODErrorCodes::RightsViolation),
+            SpicError::InternalError { .. } => Some(ODErrorCodes::InternalError),
+            SpicError::TerminalNotFound { .. } => Some(ODErrorCodes::TerminalNotFound),
+            SpicError::SubscriptionNotFound { .. } => Some(ODErrorCodes::SubscriptionNotFound),
+            SpicError::OnlineDataNotFound { .. } => Some(ODErrorCodes::OnlineDataNotFound),
+            SpicError::UnknownError { codes, .. } => codes.first().map(|code| ODErrorCodes::Unknown(*code)),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct SpicUnit {
-    #[serde(rename = "Brand")]
-    pub brand: String,
-    #[serde(rename = "Color")]
+impl Retryable for SpicError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            SpicError::NetworkError(e) => {
+                if let Some(status) = e.status() {
+                    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                } else {
+                    e.is_timeout() || e.is_connect() || e.is_request() || e.is_body()
+                }
+            }
+            SpicError::HttpStatus { status, .. } => {
+                status.is_server_error() || *status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            }
+            SpicError::ServiceBusy { .. } => true,
+            SpicError::CircuitOpen { .. } => false,
+            SpicError::InternalError { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+impl SessionError for SpicError {
+    fn is_rejected(&self) -> bool {
+        matches!(self, SpicError::Unauthorized { .. })
+    }
+
+    fn circuit_open(caller: &'static str, retry_in: std::time::Duration) -> Self {
+        SpicError::CircuitOpen {
+            caller,
+            retry_in_ms: retry_in.as_millis(),
+        }
+    }
+}
+
+/// Full URLs of the SPIC services, built from `[spic]` `base_url` and `[spic.endpoints]`.
+#[derive(Debug, Clone)]
+struct SpicEndpoints {
+    authorization_service: String,
+    authorization_logout: String,
+    units_number_service: String,
+    unit_list_service: String,
+    unit_group_service: String,
+    track_history_service: String,
+    online_data_subscribe: String,
+    online_data_get: String,
+}
+
+impl SpicEndpoints {
+    fn from_config(config: &SpicConfig) -> Self {
+        let base_url = config.base_url.trim_end_matches('/');
+        let url = |path: &str| format!("{}/{}", base_url, path.trim_start_matches('/'));
+        let endpoints = &config.endpoints;
+
+        SpicEndpoints {
+            authorization_service: url(&endpoints.login),
+            authorization_logout: url(&endpoints.logout),
+            units_number_service: url(&endpoints.units_number),
+            unit_list_service: url(&endpoints.unit_list),
+            unit_group_service: url(&endpoints.unit_groups),
+            track_history_service: url(&endpoints.track_history),
+            online_data_subscribe: url(&endpoints.online_data_subscribe),
+            online_data_get: url(&endpoints.online_data_get),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SpicUnit {
+    #[serde(rename = "Brand")]
+    pub brand: String,
+    #[serde(rename = "Color")]
     pub color: String,
     #[serde(rename = "CompanyId")]
     pub company_id: i32,
@@ -524,12 +1073,33 @@ pub struct SpicUnit {
     pub year: String,
 }
 
-impl SpicUnit {
-    fn from_json<'a>(json_data: &'a String) -> Result<SpicUnit, SpicError> {
-        match serde_json::from_str::<SpicUnit>(&json_data) {
+/// Group of units as configured in SPIC, e.g. a department or a contractor.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SpicUnitGroup {
+    #[serde(rename = "UnitGroupId", alias = "Id")]
+    pub id: i32,
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "Description", default)]
+    pub description: Option<String>,
+    #[serde(rename = "CompanyId", default)]
+    pub company_id: Option<i32>,
+    #[serde(rename = "UnitIds", default)]
+    pub unit_ids: Vec<i32>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct SpicUnitGroupList {
+    #[serde(rename = "UnitGroups")]
+    groups: Vec<SpicUnitGroup>,
+}
+
+impl SpicUnitGroupList {
+    fn from_json(json_data: &str) -> Result<SpicUnitGroupList, SpicError> {
+        match serde_json::from_str(json_data) {
             Ok(data) => Ok(data),
             Err(e) => Err(SpicError::JsonError {
-                caller: "SpicUnit::from_json",
+                caller: "SpicUnitGroupList::from_json",
                 source: e,
                 data: json_data.to_string(),
             }),
@@ -544,8 +1114,8 @@ struct SpicUnitList {
 }
 
 impl SpicUnitList {
-    fn from_json<'a>(json_data: &'a String) -> Result<SpicUnitList, SpicError> {
-        match serde_json::from_str(&json_data) {
+    fn from_json(json_data: &String) -> Result<SpicUnitList, SpicError> {
+        match serde_json::from_str(json_data) {
             Ok(data) => Ok(data),
             Err(e) => {
                 println!("{}", e);
@@ -596,6 +1166,44 @@ impl SubscriptionManager {
             None
         }
     }
+
+    /// Units whose subscription is still live but about to expire.
+    fn due_for_renewal(&self) -> Vec<i32> {
+        let mut unit_ids = self
+            .subscriptions
+            .iter()
+            .filter(|(_, subscription)| !subscription.is_expired() && subscription.needs_renewal())
+            .map(|(unit_id, _)| *unit_id)
+            .collect::<Vec<_>>();
+        unit_ids.sort_unstable();
+        unit_ids
+    }
+
+    /// Like `get_subscription`, but also skips subscriptions that are about to
+    /// expire, so they get renewed before SPIC drops them.
+    fn get_live_subscription(&self, unit_id: i32) -> Option<&Subscription> {
+        self.get_subscription(unit_id)
+            .filter(|subscription| !subscription.needs_renewal())
+    }
+
+    /// Splits units into groups sharing a live subscription and units that
+    /// have to be subscribed again.
+    fn group_by_live_subscription(&self, unit_ids: &[i32]) -> (HashMap<String, Vec<i32>>, Vec<i32>) {
+        let mut live: HashMap<String, Vec<i32>> = HashMap::new();
+        let mut unsubscribed = Vec::new();
+
+        for unit_id in unit_ids {
+            match self.get_live_subscription(*unit_id) {
+                Some(subscription) => live
+                    .entry(subscription.uuid.clone())
+                    .or_default()
+                    .push(*unit_id),
+                None => unsubscribed.push(*unit_id),
+            }
+        }
+
+        (live, unsubscribed)
+    }
 }
 
 #[derive(Debug)]
@@ -614,17 +1222,48 @@ impl Subscription {
     fn is_expired(&self) -> bool {
         let now = Utc::now();
         let diff = now - self.created_at;
-        diff.num_minutes() > 10
+        diff.num_minutes() > SUBSCRIPTION_LIFETIME_MINUTES
+    }
+
+    fn needs_renewal(&self) -> bool {
+        let renew_at = self.created_at + Duration::minutes(SUBSCRIPTION_LIFETIME_MINUTES)
+            - Duration::seconds(SUBSCRIPTION_RENEW_MARGIN_SECONDS);
+        Utc::now() >= renew_at
     }
 }
 
-enum ODErrorCodes {
-    BadRequest = 200,
-    RightsViolation = 201,
-    InternalError = 202,
-    TerminalNotFound = 203,
-    SubscriptionNotFound = 204,
-    OnlineDataNotFound = 205,
+/// Error codes of `OnlineDataService` responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ODErrorCodes {
+    BadRequest,
+    RightsViolation,
+    InternalError,
+    TerminalNotFound,
+    SubscriptionNotFound,
+    OnlineDataNotFound,
+    Unknown(i32),
+}
+
+impl ODErrorCodes {
+    /// Codes about a single unit rather than the request or subscription.
+    /// Polling such a unit again gives the same answer.
+    pub fn is_unit_error(&self) -> bool {
+        matches!(self, ODErrorCodes::TerminalNotFound | ODErrorCodes::OnlineDataNotFound)
+    }
+}
+
+impl From<i32> for ODErrorCodes {
+    fn from(code: i32) -> Self {
+        match code {
+            200 => ODErrorCodes::BadRequest,
+            201 => ODErrorCodes::RightsViolation,
+            202 => ODErrorCodes::InternalError,
+            203 => ODErrorCodes::TerminalNotFound,
+            204 => ODErrorCodes::SubscriptionNotFound,
+            205 => ODErrorCodes::OnlineDataNotFound,
+            code => ODErrorCodes::Unknown(code),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
@@ -650,8 +1289,9 @@ struct ODResponse {
 }
 
 impl ODResponse {
-    fn from_json<'a>(json_data: &'a String) -> Result<ODResponse, SpicError> {
-        match serde_json::from_str(&json_data) {
+    #[cfg(test)]
+    fn from_json(json_data: &String) -> Result<ODResponse, SpicError> {
+        match serde_json::from_str(json_data) {
             Ok(data) => Ok(data),
             Err(e) => Err(SpicError::JsonError {
                 caller: "OnlineDataResponse::from_json",
@@ -663,6 +1303,40 @@ impl ODResponse {
     fn is_ok(&self) -> bool {
         self.state.is_ok()
     }
+
+    fn has_only_unit_errors(&self) -> bool {
+        let codes = self.state.error_codes();
+        !codes.is_empty() && codes.iter().all(ODErrorCodes::is_unit_error)
+    }
+
+    /// Pairs the returned data with unit ids. Items without `UnitId` are
+    /// matched with `Targets` by position. The targets left without data
+    /// get the unit error codes, see `ODResponseState::unit_error_codes`.
+    fn into_received(self) -> ODReceived {
+        let targets = self.online_data_collection.targets;
+
+        let data: HashMap<i32, OnlineData> = self
+            .online_data_collection
+            .data_collection
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, data)| {
+                data.unit_id
+                    .or_else(|| targets.get(i).copied())
+                    .map(|unit_id| (unit_id, data))
+            })
+            .collect();
+
+        let without_data = targets
+            .iter()
+            .filter(|id| !data.contains_key(id))
+            .copied()
+            .collect::<Vec<_>>();
+        let failed = self.state.unit_error_codes(&without_data);
+
+        ODReceived { data, failed }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -699,59 +1373,261 @@ impl ODResponseState {
     fn is_ok(&self) -> bool {
         self.status.value == ODStatus::Ok
     }
+
+    fn is_busy(&self) -> bool {
+        self.status.value == ODStatus::Busy
+    }
+
+    fn is_partial(&self) -> bool {
+        self.status.value == ODStatus::PartialOk
+    }
+
+    fn error_codes(&self) -> Vec<ODErrorCodes> {
+        self.error_codes.iter().map(|code| ODErrorCodes::from(*code)).collect()
+    }
+
+    /// Unit error codes paired with the units they belong to. The codes name
+    /// no unit and their order is not verified against SPIC, so they are
+    /// only paired when the order does not matter: one code per unit, all of
+    /// them the same. Request level codes never are.
+    fn unit_error_codes(&self, unit_ids: &[i32]) -> HashMap<i32, ODErrorCodes> {
+        let codes = self
+            .error_codes()
+            .into_iter()
+            .filter(ODErrorCodes::is_unit_error)
+            .collect::<Vec<_>>();
+
+        match codes.first() {
+            Some(code) if codes.len() == unit_ids.len() && codes.iter().all(|other| other == code) => {
+                unit_ids.iter().map(|unit_id| (*unit_id, *code)).collect()
+            }
+            _ => HashMap::new(),
+        }
+    }
+}
+
+/// What one `GetOnlineData` call returned.
+#[derive(Debug, Default)]
+struct ODReceived {
+    data: HashMap<i32, OnlineData>,
+    /// Units SPIC reported a unit error code for
+    failed: HashMap<i32, ODErrorCodes>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct OnlineData {
+    #[serde(rename = "UnitId", default)]
+    pub unit_id: Option<i32>,
     #[serde(rename = "Address")]
-    address: String,
+    pub address: String,
     #[serde(
         rename = "ConnectionDateTime",
         deserialize_with = "deserialize_ms_date"
     )]
-    connection_date_time: DateTime<Utc>,
+    pub connection_date_time: DateTime<FixedOffset>,
     #[serde(rename = "DeviceId")]
-    device_id: DeviceId,
+    pub device_id: DeviceId,
     #[serde(rename = "IsNavigationValid")]
-    is_navigation_valid: bool,
+    pub is_navigation_valid: bool,
     #[serde(rename = "LastMessageTime", deserialize_with = "deserialize_ms_date")]
-    last_message_time: DateTime<Utc>,
+    pub last_message_time: DateTime<FixedOffset>,
     #[serde(rename = "Navigation")]
-    navigation: NavigationData,
+    pub navigation: NavigationData,
     #[serde(rename = "NavigationTime", deserialize_with = "deserialize_ms_date")]
-    navigation_time: DateTime<Utc>,
+    pub navigation_time: DateTime<FixedOffset>,
     #[serde(rename = "TotalMessages")]
-    total_messages: i32,
+    pub total_messages: i32,
+    /// Everything else SPIC sent, e.g. sensors and extension data.
+    #[serde(flatten)]
+    pub extras: serde_json::Map<String, serde_json::Value>,
+}
+
+impl OnlineData {
+    /// Looks a sensor up by name, either as a top level field or as an
+    /// entry of the `Sensors` list (`{"Name": ..., "Value": ...}`).
+    pub fn sensor(&self, name: &str) -> Option<&serde_json::Value> {
+        if let Some(value) = self.extras.get(name) {
+            return Some(value);
+        }
+
+        self.extras
+            .get("Sensors")?
+            .as_array()?
+            .iter()
+            .find(|sensor| {
+                sensor
+                    .get("Name")
+                    .and_then(|sensor_name| sensor_name.as_str())
+                    .is_some_and(|sensor_name| sensor_name.eq_ignore_ascii_case(name))
+            })
+            .and_then(|sensor| sensor.get("Value"))
+    }
+
+    pub fn ignition(&self) -> Option<bool> {
+        match self.sensor("Ignition")? {
+            serde_json::Value::Bool(value) => Some(*value),
+            serde_json::Value::Number(value) => value.as_f64().map(|value| value != 0.0),
+            serde_json::Value::String(value) => match value.to_lowercase().as_str() {
+                "true" | "1" | "on" => Some(true),
+                "false" | "0" | "off" => Some(false),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Board voltage in volts.
+    pub fn voltage(&self) -> Option<f64> {
+        match self.sensor("Voltage").or_else(|| self.sensor("PowerVoltage"))? {
+            serde_json::Value::Number(value) => value.as_f64(),
+            serde_json::Value::String(value) => value.replace(',', ".").parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Why a requested unit has no online data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingReason {
+    /// SPIC answered with this error code, for the unit or its whole chunk
+    Code(ODErrorCodes),
+    /// No data before `subscription_timeout`
+    Timeout,
+    /// SPIC stayed busy through every retry
+    Busy,
+    /// SPIC's answer could not be read
+    InvalidResponse,
+}
+
+impl MissingReason {
+    /// Why the units of a failed subscribe or get online data request have
+    /// no data. `None` for failures of the connection or the session, those
+    /// hit every other request as well.
+    fn from_error(error: &SpicError) -> Option<MissingReason> {
+        if let Some(code) = error.error_code() {
+            return Some(MissingReason::Code(code));
+        }
+
+        match error {
+            SpicError::SubscriptionTimeout { .. } => Some(MissingReason::Timeout),
+            SpicError::ServiceBusy { .. } => Some(MissingReason::Busy),
+            SpicError::UnknownError { .. } | SpicError::JsonError { .. } | SpicError::DateParseError(_) => {
+                Some(MissingReason::InvalidResponse)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Result of a multi-unit online data request.
+#[derive(Debug, Default)]
+pub struct OnlineDataBatch {
+    pub data: HashMap<i32, OnlineData>,
+    /// Requested units SPIC returned no data for.
+    pub missing: HashMap<i32, MissingReason>,
+}
+
+impl OnlineDataBatch {
+    fn with_capacity(capacity: usize) -> Self {
+        OnlineDataBatch {
+            data: HashMap::with_capacity(capacity),
+            missing: HashMap::new(),
+        }
+    }
+
+    /// Lists the units of a failed request as missing, unless the failure
+    /// is one the whole batch has to fail with.
+    fn fail(&mut self, unit_ids: &[i32], error: SpicError) -> Result<(), SpicError> {
+        let Some(reason) = MissingReason::from_error(&error) else {
+            return Err(error);
+        };
+
+        println!("Online data request for units {:?} failed: {}", unit_ids, error);
+        self.missing.extend(unit_ids.iter().map(|unit_id| (*unit_id, reason)));
+        Ok(())
+    }
+
+    fn merge(&mut self, other: OnlineDataBatch) {
+        self.data.extend(other.data);
+        self.missing.extend(other.missing);
+    }
 }
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
-struct DeviceId {
+pub struct DeviceId {
     #[serde(rename = "Protocol")]
-    protocol: HashMap<String, String>,
+    pub protocol: DeviceProtocol,
     #[serde(rename = "SerialId")]
-    serial_id: String,
+    pub serial_id: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct DeviceProtocol {
+    #[serde(rename = "Name", default)]
+    pub name: Option<String>,
+    #[serde(rename = "Version", default)]
+    pub version: Option<String>,
+    #[serde(flatten)]
+    pub extras: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "PascalCase")]
-struct NavigationData {
-    altitude_meters: i32,
-    angle: i32,
-    hardware_validation: Option<String>,
-    location: Location,
-    navigation_system_type: String,
-    satellites_count: i8,
-    speed: i32,
+pub struct NavigationData {
+    pub altitude_meters: i32,
+    pub angle: i32,
+    pub hardware_validation: Option<String>,
+    pub location: Location,
+    pub navigation_system_type: String,
+    pub satellites_count: i8,
+    pub speed: i32,
 }
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(rename_all = "PascalCase")]
-struct Location {
-    latitude: f64,
-    longitude: f64,
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// One stored message of a unit track.
+#[derive(Debug, Serialize, Clone)]
+pub struct TrackPoint {
+    pub unit_id: i32,
+    pub time: DateTime<FixedOffset>,
+    pub navigation: NavigationData,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryMessage {
+    #[serde(rename = "MessageTime", deserialize_with = "deserialize_ms_date")]
+    time: DateTime<FixedOffset>,
+    #[serde(rename = "Navigation", default)]
+    navigation: Option<NavigationData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HistoryResponse {
+    #[serde(rename = "Messages", default)]
+    messages: Vec<HistoryMessage>,
+}
+
+impl HistoryResponse {
+    fn from_json(json_data: &str) -> Result<HistoryResponse, SpicError> {
+        match serde_json::from_str(json_data) {
+            Ok(data) => Ok(data),
+            Err(e) => Err(SpicError::JsonError {
+                caller: "HistoryResponse::from_json",
+                source: e,
+                data: json_data.to_string(),
+            }),
+        }
+    }
 }
 
 impl OnlineData {
-    fn from_json<'a>(json_data: &'a String) -> Result<OnlineData, SpicError> {
-        match serde_json::from_str::<OnlineData>(&json_data) {
+    #[cfg(test)]
+    fn from_json(json_data: &String) -> Result<OnlineData, SpicError> {
+        match serde_json::from_str::<OnlineData>(json_data) {
             Ok(data) => Ok(data),
             Err(e) => Err(SpicError::JsonError {
                 caller: "OnlineData::from_json",
@@ -773,56 +1649,316 @@ pub struct AuthResponse {
     #[serde(rename = "UserName")]
     user_name: String,
     #[serde(rename = "SessionId")]
-    session_id: Cow<'static, String>,
+    session_id: Secret,
     #[serde(rename = "ExpireDate")]
     #[serde(deserialize_with = "deserialize_ms_date")]
-    expire_date: DateTime<Utc>,
+    expire_date: DateTime<FixedOffset>,
 }
 
 impl AuthResponse {
-    fn from_json<'a>(json_data: &'a String) -> Result<AuthResponse, SpicError> {
-        if let Ok(data) = serde_json::from_str::<AuthResponse>(&json_data) {
+    fn from_json(json_data: &str) -> Result<AuthResponse, SpicError> {
+        if let Ok(data) = serde_json::from_str::<AuthResponse>(json_data) {
             Ok(data)
         } else {
-            return Err(SpicError::AuthenticationError(
+            Err(SpicError::AuthenticationError(
                 "Unable to parse authentication response".to_string(),
-            ));
+            ))
         }
     }
-    fn new() -> Self {
-        AuthResponse {
-            is_authorized: false,
-            is_authenticated: false,
-            user_id: 0,
-            user_name: "".to_string(),
-            session_id: Cow::Owned("".to_string()),
-            expire_date: Utc::now(),
+}
+
+/// Turns a non-OK response into `SpicError::HttpStatus`, keeping the body for diagnostics.
+async fn http_status_error(endpoint: &str, response: reqwest::Response) -> SpicError {
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .unwrap_or_else(|e| format!("<failed to read body: {}>", e));
+
+    SpicError::HttpStatus {
+        endpoint: endpoint.to_string(),
+        status,
+        body,
+    }
+}
+
+/// Builds a [`SpicClient`] from an explicit [`SpicConfig`] instead of the
+/// global config, so several clients with different settings can coexist.
+#[derive(Debug)]
+pub struct SpicClientBuilder {
+    config: SpicConfig,
+    retry: RetryPolicy,
+    rate_limits: RateLimitsConfig,
+    circuit_breaker: CircuitBreakerConfig,
+    token_store: Arc<dyn TokenStore>,
+    connect_timeout: Option<std::time::Duration>,
+    request_timeout: Option<std::time::Duration>,
+    proxy: Option<String>,
+    user_agent: String,
+    http_client: Option<Client>,
+    transport: Option<Transport>,
+}
+
+impl SpicClientBuilder {
+    pub fn new(config: SpicConfig) -> Self {
+        SpicClientBuilder {
+            connect_timeout: config.connect_timeout_ms.map(std::time::Duration::from_millis),
+            proxy: config.proxy.clone(),
+            user_agent: config.user_agent.clone().unwrap_or_else(|| DEFAULT_USER_AGENT.to_string()),
+            config,
+            retry: RetryPolicy::default(),
+            rate_limits: RateLimitsConfig::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            token_store: Arc::new(MemoryTokenStore::default()),
+            request_timeout: None,
+            http_client: None,
+            transport: None,
         }
     }
+
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.config.base_url = base_url.into();
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn rate_limits(mut self, rate_limits: RateLimitsConfig) -> Self {
+        self.rate_limits = rate_limits;
+        self
+    }
+
+    pub fn circuit_breaker(mut self, circuit_breaker: CircuitBreakerConfig) -> Self {
+        self.circuit_breaker = circuit_breaker;
+        self
+    }
+
+    /// Where the session is cached between runs, in memory only by default.
+    pub fn token_store(mut self, token_store: Arc<dyn TokenStore>) -> Self {
+        self.token_store = token_store;
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Total time for one request, from connecting until the body is read.
+    pub fn request_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Proxy URL for every request, e.g. `http://proxy.local:3128`.
+    pub fn proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.proxy = Some(proxy.into());
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Uses a ready `reqwest::Client`, timeouts, proxy and user agent of the
+    /// builder are ignored then.
+    pub fn http_client(mut self, client: Client) -> Self {
+        self.http_client = Some(client);
+        self
+    }
+
+    /// Replaces the transport from `[spic.transport]`.
+    pub fn transport(mut self, transport: Transport) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    pub fn build(self) -> Result<SpicClient, SpicError> {
+        let client = match self.http_client {
+            Some(client) => client,
+            None => {
+                let mut builder = Client::builder().user_agent(self.user_agent);
+
+                if let Some(timeout) = self.connect_timeout {
+                    builder = builder.connect_timeout(timeout);
+                }
+                if let Some(timeout) = self.request_timeout {
+                    builder = builder.timeout(timeout);
+                }
+                if let Some(proxy) = self.proxy {
+                    builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+                }
+
+                builder.build()?
+            }
+        };
+
+        let config = self.config;
+        let transport = self
+            .transport
+            .unwrap_or_else(|| Transport::from_config(&config.transport, &config.account));
+
+        // Fixtures are replayed whatever password is sent, none needs to be set up
+        let password = match config.password.resolve(&config.login) {
+            Err(e) if transport.mode() == TransportMode::Replay => {
+                println!("Replaying fixtures without a password: {}", e);
+                Secret::default()
+            }
+            password => password?,
+        };
+
+        Ok(SpicClient {
+            client,
+            session: Arc::new(Session::new(&self.circuit_breaker)),
+            subman: Arc::new(RwLock::new(SubscriptionManager::new())),
+            limits: Arc::new(RateLimits::new(&self.rate_limits)),
+            token_store: self.token_store,
+            transport: Arc::new(transport),
+            timezone: config.timezone,
+            endpoints: Arc::new(SpicEndpoints::from_config(&config)),
+            retry: self.retry,
+            subscription_timeout: std::time::Duration::from_millis(config.subscription_timeout_ms),
+            subscription_poll_interval: std::time::Duration::from_millis(
+                config.subscription_poll_interval_ms,
+            ),
+            online_data_chunk_size: config.online_data_chunk_size.max(1),
+            token_refresh_margin: Duration::seconds(config.token_refresh_margin_s),
+            history_window: Duration::hours(config.history_window_hours.max(1)),
+            history_page_size: config.history_page_size.max(1),
+            config: Arc::new(config),
+            password,
+        })
+    }
 }
 
-pub fn init_client() -> SpicClient {
-    let mut headers = header::HeaderMap::new();
-    headers.insert(
-        "Content-Type",
-        header::HeaderValue::from_static("application/json"),
-    );
-    headers.insert(
-        "Accept",
-        header::HeaderValue::from_static("application/json; charset=utf-8"),
-    );
+/// One client per account of the global `[spic]` section, configured from
+/// `[retry]`, `[server]` and the other global sections. The token store is
+/// shared, it keeps the sessions apart by login.
+pub fn init_clients() -> Result<Vec<SpicClient>, SpicError> {
+    let request_timeout = conf!(server).request_timeout.max(0) as u64;
+    let token_store = token_store::from_config(&conf!(token_store))?;
+
+    conf!(spic)
+        .account_configs()
+        .into_iter()
+        .map(|config| {
+            SpicClientBuilder::new(config)
+                .retry(RetryPolicy::from_config(&conf!(retry)))
+                .rate_limits(conf!(rate_limit))
+                .circuit_breaker(conf!(circuit_breaker))
+                .token_store(Arc::clone(&token_store))
+                .request_timeout(std::time::Duration::from_millis(request_timeout))
+                .build()
+        })
+        .collect()
+}
 
-    let client = Client::builder()
-        .user_agent(DEFAULT_USER_AGENT)
-        .default_headers(headers)
-        .build()
-        .expect("Unable to create reqwest client");
+/// Non-empty strings only, SPIC sends empty strings for unset fields.
+fn non_empty(value: &str) -> Option<String> {
+    (!value.is_empty()).then(|| value.to_string())
+}
 
-    dbg!(&client);
+/// Serialized object without the fields already mapped elsewhere.
+fn extras_without<T: Serialize>(value: &T, mapped: &[&str]) -> serde_json::Map<String, serde_json::Value> {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::Object(mut fields)) => {
+            for name in mapped {
+                fields.remove(*name);
+            }
+            fields
+        }
+        _ => serde_json::Map::new(),
+    }
+}
 
-    SpicClient::new(client)
+impl From<SpicUnit> for Unit {
+    fn from(unit: SpicUnit) -> Self {
+        Unit {
+            id: unit.id.into(),
+            name: unit.name.clone(),
+            registration: non_empty(&unit.state_number),
+            vin: non_empty(&unit.vin),
+            model: non_empty(&unit.model),
+            extras: extras_without(&unit, &["UnitId", "Name", "StateNumber", "VinNumber", "Model"]),
+        }
+    }
 }
 
+impl OnlineData {
+    /// Ignition and voltage are added to the extras as `ignition` and
+    /// `voltage`, whichever way SPIC reported them.
+    fn into_position(self, unit_id: i32) -> Position {
+        let navigation = &self.navigation;
+        let mut extras = extras_without(&self, &["UnitId", "Navigation", "NavigationTime", "IsNavigationValid"]);
+        if let Some(ignition) = self.ignition() {
+            extras.insert("ignition".to_string(), ignition.into());
+        }
+        if let Some(voltage) = self.voltage() {
+            extras.insert("voltage".to_string(), voltage.into());
+        }
+
+        Position {
+            unit_id: unit_id.into(),
+            time: self.navigation_time.with_timezone(&Utc),
+            latitude: navigation.location.latitude,
+            longitude: navigation.location.longitude,
+            speed: Some(navigation.speed.into()),
+            course: Some(navigation.angle),
+            altitude: Some(navigation.altitude_meters.into()),
+            satellites: Some(navigation.satellites_count.into()),
+            is_valid: self.is_navigation_valid,
+            extras,
+        }
+    }
+}
+
+impl TelematicsProvider for SpicClient {
+    fn provider(&self) -> &'static str {
+        "spic"
+    }
+
+    fn account(&self) -> &str {
+        SpicClient::account(self)
+    }
+
+    fn is_unavailable(&self) -> bool {
+        self.is_circuit_open()
+    }
+
+    async fn authenticate(&self) -> Result<(), TelematicsError> {
+        Ok(SpicClient::authenticate(self).await?)
+    }
+
+    async fn logout(&self) -> Result<(), TelematicsError> {
+        Ok(SpicClient::logout(self).await?)
+    }
+
+    async fn units(&self) -> Result<Vec<Unit>, TelematicsError> {
+        Ok(self.unit_list().await?.into_iter().map(Unit::from).collect())
+    }
+
+    async fn positions(&self, unit_ids: &[i64]) -> Result<HashMap<i64, Position>, TelematicsError> {
+        // SPIC ids are 32 bit, anything else cannot be one of its units
+        let spic_ids = unit_ids
+            .iter()
+            .filter_map(|id| i32::try_from(*id).ok())
+            .collect::<Vec<_>>();
+
+        let batch = self.get_online_data_many(&spic_ids).await?;
+
+        Ok(batch
+            .data
+            .into_iter()
+            .map(|(unit_id, data)| (unit_id.into(), data.into_position(unit_id)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
 fn is_normal<T: Send + Sync + Unpin + Sized>() {}
 
 /// implement the necessary traits (`Send`, `Sync`, `Unpin`, and `Sized`)
@@ -831,7 +1967,7 @@ fn is_normal<T: Send + Sync + Unpin + Sized>() {}
 fn test_is_normal() {
     is_normal::<SpicClient>();
     is_normal::<AuthToken>();
-    is_normal::<SpicEndpoint>();
+    is_normal::<SpicEndpoints>();
 }
 
 #[cfg(test)]
@@ -920,6 +2056,39 @@ mod subscription_tests {
         assert!(handler.is_exist(2));
     }
 
+    #[test]
+    fn test_subscription_about_to_expire_is_not_live() {
+        let mut handler = SubscriptionManager::new();
+        handler.add_subscription(1, "uuid-1".to_string());
+        handler.subscriptions.insert(
+            2,
+            Subscription {
+                uuid: "uuid-2".to_string(),
+                created_at: Utc::now() - Duration::minutes(SUBSCRIPTION_LIFETIME_MINUTES)
+                    + Duration::seconds(SUBSCRIPTION_RENEW_MARGIN_SECONDS / 2),
+            },
+        );
+
+        assert!(handler.get_subscription(2).is_some());
+        assert!(handler.get_live_subscription(1).is_some());
+        assert!(handler.get_live_subscription(2).is_none());
+        assert_eq!(handler.due_for_renewal(), vec![2]);
+    }
+
+    #[test]
+    fn test_group_by_live_subscription() {
+        let mut handler = SubscriptionManager::new();
+        handler.add_subscription(1, "uuid-a".to_string());
+        handler.add_subscription(2, "uuid-a".to_string());
+        handler.add_subscription(3, "uuid-b".to_string());
+
+        let (live, unsubscribed) = handler.group_by_live_subscription(&[1, 2, 3, 4]);
+
+        assert_eq!(live.get("uuid-a").unwrap(), &vec![1, 2]);
+        assert_eq!(live.get("uuid-b").unwrap(), &vec![3]);
+        assert_eq!(unsubscribed, vec![4]);
+    }
+
     #[test]
     fn test_get_subscription() {
         let mut handler = SubscriptionManager::new();
@@ -931,3 +2100,541 @@ mod subscription_tests {
         assert!(handler.get_subscription(3).is_none());
     }
 }
+
+#[cfg(test)]
+mod online_data_tests {
+    use super::builder_tests::replay_client;
+    use super::*;
+
+    const SUBSCRIBED: &str = r#"{"SessionId":{"Id":"0f8fad5b-d9cb-469f-a165-70867728950e"},"State":{"ErrorCodes":[],"Status":{"Value":"Ok"}}}"#;
+
+    fn od_response(status: &str, data_collection: &str) -> ODResponse {
+        let json = format!(
+            r#"{{
+                "OnlineDataCollection": {{ "DataCollection": {}, "Targets": [1, 2] }},
+                "State": {{ "ErrorCodes": [], "Status": {{ "Value": "{}" }} }}
+            }}"#,
+            data_collection, status
+        );
+        ODResponse::from_json(&json).unwrap()
+    }
+
+    pub(super) fn online_data(unit_id: Option<i32>) -> String {
+        let unit_id = unit_id.map_or("null".to_string(), |id| id.to_string());
+        format!(
+            r#"{{
+                "UnitId": {},
+                "Address": "",
+                "ConnectionDateTime": "/Date(1735972469975+0300)/",
+                "DeviceId": {{ "Protocol": {{}}, "SerialId": "1" }},
+                "IsNavigationValid": true,
+                "LastMessageTime": "/Date(1735972469975+0300)/",
+                "Navigation": {{
+                    "AltitudeMeters": 0, "Angle": 0, "HardwareValidation": null,
+                    "Location": {{ "Latitude": 56.8, "Longitude": 60.6 }},
+                    "NavigationSystemType": "Gps", "SatellitesCount": 7, "Speed": 0
+                }},
+                "NavigationTime": "/Date(1735972469975+0300)/",
+                "TotalMessages": 1
+            }}"#,
+            unit_id
+        )
+    }
+
+    #[test]
+    fn test_error_codes_are_typed() {
+        let json = r#"{
+            "OnlineDataCollection": { "DataCollection": null, "Targets": [1] },
+            "State": { "ErrorCodes": [203, 204, 299], "Status": { "Value": "Error" } }
+        }"#
+        .to_string();
+        let response = ODResponse::from_json(&json).unwrap();
+
+        assert_eq!(
+            response.state.error_codes(),
+            vec![
+                ODErrorCodes::TerminalNotFound,
+                ODErrorCodes::SubscriptionNotFound,
+                ODErrorCodes::Unknown(299)
+            ]
+        );
+
+        let error = SpicError::from_codes("test", &response.state.error_codes(), &[1]);
+        assert!(matches!(error, SpicError::SubscriptionNotFound { .. }));
+        assert_eq!(error.error_code(), Some(ODErrorCodes::SubscriptionNotFound));
+        assert!(!error.is_retryable());
+
+        let internal = SpicError::from_codes("test", &[ODErrorCodes::InternalError], &[1]);
+        assert!(internal.is_retryable());
+
+        let unknown = SpicError::from_codes("test", &[ODErrorCodes::Unknown(299)], &[1]);
+        assert_eq!(unknown.error_code(), Some(ODErrorCodes::Unknown(299)));
+    }
+
+    #[test]
+    fn test_unit_codes_name_the_unit_of_single_unit_requests() {
+        let codes = [ODErrorCodes::TerminalNotFound];
+
+        assert!(matches!(
+            SpicError::from_codes("test", &codes, &[7]),
+            SpicError::TerminalNotFound { unit_id: Some(7), .. }
+        ));
+        assert!(matches!(
+            SpicError::from_codes("test", &codes, &[7, 8]),
+            SpicError::TerminalNotFound { unit_id: None, .. }
+        ));
+        assert!(matches!(
+            SpicError::from_codes("test", &[ODErrorCodes::RightsViolation, ODErrorCodes::OnlineDataNotFound], &[7]),
+            SpicError::RightsViolation { unit_id: Some(7), .. }
+        ));
+    }
+
+    #[test]
+    fn test_unit_error_codes_go_to_targets_without_data() {
+        let received = |codes: &str| {
+            let json = format!(
+                r#"{{
+                    "OnlineDataCollection": {{ "DataCollection": [{}], "Targets": [1, 2, 3] }},
+                    "State": {{ "ErrorCodes": [{}], "Status": {{ "Value": "PartialOk" }} }}
+                }}"#,
+                online_data(Some(1)),
+                codes
+            );
+            ODResponse::from_json(&json).unwrap().into_received()
+        };
+
+        let same = received("205, 205");
+        assert!(same.data.contains_key(&1));
+        assert_eq!(
+            same.failed,
+            HashMap::from([(2, ODErrorCodes::OnlineDataNotFound), (3, ODErrorCodes::OnlineDataNotFound)])
+        );
+
+        // Which unit each code belongs to is unknown, and so is a missing code's unit
+        assert!(received("205, 203").failed.is_empty());
+        assert!(received("205").failed.is_empty());
+
+        let lost = r#"{
+            "OnlineDataCollection": { "DataCollection": null, "Targets": [1] },
+            "State": { "ErrorCodes": [204], "Status": { "Value": "Error" } }
+        }"#
+        .to_string();
+        let response = ODResponse::from_json(&lost).unwrap();
+
+        assert!(!response.has_only_unit_errors());
+        assert!(response.into_received().failed.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_fields_are_kept() {
+        let mut json: serde_json::Value = serde_json::from_str(&online_data(Some(1))).unwrap();
+        json["Sensors"] = serde_json::json!([
+            { "Name": "Ignition", "Value": 1 },
+            { "Name": "Voltage", "Value": "12,6" }
+        ]);
+        json["Odometer"] = serde_json::json!(1500.5);
+        json["DeviceId"]["Protocol"] = serde_json::json!({ "Name": "Galileo", "Port": 20100 });
+
+        let data = OnlineData::from_json(&json.to_string()).unwrap();
+
+        assert_eq!(data.ignition(), Some(true));
+        assert_eq!(data.voltage(), Some(12.6));
+        assert_eq!(data.extras["Odometer"], serde_json::json!(1500.5));
+        assert_eq!(data.device_id.protocol.name.as_deref(), Some("Galileo"));
+        assert_eq!(data.device_id.protocol.extras["Port"], serde_json::json!(20100));
+    }
+
+    #[test]
+    fn test_missing_sensors() {
+        let data = OnlineData::from_json(&online_data(Some(1))).unwrap();
+
+        assert!(data.extras.is_empty());
+        assert_eq!(data.ignition(), None);
+        assert_eq!(data.voltage(), None);
+    }
+
+    #[test]
+    fn test_empty_collection_has_no_unit_data() {
+        assert!(od_response("Ok", "null").into_received().data.is_empty());
+        assert!(od_response("Busy", "[]").into_received().data.is_empty());
+    }
+
+    #[test]
+    fn test_unit_data_keyed_by_unit_id() {
+        let collection = format!("[{}]", online_data(Some(2)));
+        let data = od_response("PartialOk", &collection).into_received().data;
+
+        assert_eq!(data.len(), 1);
+        assert!(data.contains_key(&2));
+    }
+
+    #[test]
+    fn test_unit_data_falls_back_to_targets() {
+        let collection = format!("[{}, {}]", online_data(None), online_data(None));
+        let data = od_response("Ok", &collection).into_received().data;
+
+        assert!(data.contains_key(&1));
+        assert!(data.contains_key(&2));
+    }
+
+    #[tokio::test]
+    async fn test_units_with_error_codes_are_not_polled_again() {
+        let online = format!(
+            r#"{{"OnlineDataCollection":{{"DataCollection":[{}],"Targets":[1,2]}},"State":{{"ErrorCodes":[205],"Status":{{"Value":"PartialOk"}}}}}}"#,
+            online_data(Some(1))
+        );
+        let (client, _fixtures) = replay_client(&[
+            ("post_spic_onlinedataservice_rest_subscribe.0", SUBSCRIBED),
+            ("post_spic_onlinedataservice_rest_getonlinedata.0", &online),
+        ]);
+
+        let started = Instant::now();
+        let batch = client.get_online_data_many(&[1, 2]).await.unwrap();
+
+        assert!(started.elapsed() < client.subscription_timeout);
+        assert!(batch.data.contains_key(&1));
+        assert_eq!(batch.missing, HashMap::from([(2, MissingReason::Code(ODErrorCodes::OnlineDataNotFound))]));
+
+        assert!(client.get_online_data(1).await.is_ok());
+        assert!(matches!(
+            client.get_online_data(2).await,
+            Err(SpicError::NoOnlineData {
+                unit_id: 2,
+                reason: MissingReason::Code(ODErrorCodes::OnlineDataNotFound)
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_failed_chunk_keeps_the_other_chunks() {
+        let rejected = r#"{"SessionId":{"Id":"00000000-0000-0000-0000-000000000000"},"State":{"ErrorCodes":[201],"Status":{"Value":"Error"}}}"#;
+        let online = format!(
+            r#"{{"OnlineDataCollection":{{"DataCollection":[{}],"Targets":[101]}},"State":{{"ErrorCodes":[],"Status":{{"Value":"Ok"}}}}}}"#,
+            online_data(Some(101))
+        );
+        // The first chunk of 100 units is rejected, the second one is subscribed
+        let (client, _fixtures) = replay_client(&[
+            ("post_spic_onlinedataservice_rest_subscribe.0", rejected),
+            ("post_spic_onlinedataservice_rest_subscribe.1", SUBSCRIBED),
+            ("post_spic_onlinedataservice_rest_getonlinedata.0", &online),
+        ]);
+        let unit_ids = (1..=101).collect::<Vec<i32>>();
+
+        let batch = client.get_online_data_many(&unit_ids).await.unwrap();
+
+        assert_eq!(batch.data.keys().collect::<Vec<_>>(), [&101]);
+        assert_eq!(batch.missing.len(), 100);
+        assert_eq!(batch.missing[&1], MissingReason::Code(ODErrorCodes::RightsViolation));
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions_about_to_expire_are_renewed() {
+        let (client, _fixtures) = replay_client(&[("post_spic_onlinedataservice_rest_subscribe.0", SUBSCRIBED)]);
+        let created_at = Utc::now() - Duration::minutes(SUBSCRIPTION_LIFETIME_MINUTES)
+            + Duration::seconds(SUBSCRIPTION_RENEW_MARGIN_SECONDS / 2);
+        client.subman.write().unwrap().subscriptions.insert(
+            1,
+            Subscription {
+                uuid: "old".to_string(),
+                created_at,
+            },
+        );
+
+        client.renew_subscriptions().await;
+
+        let subman = client.subman.read().unwrap();
+        let renewed = subman.get_live_subscription(1).unwrap();
+        assert_eq!(renewed.uuid, "0f8fad5b-d9cb-469f-a165-70867728950e");
+    }
+}
+
+#[cfg(test)]
+mod date_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_date_with_offset() {
+        let date = parse_ms_date("/Date(1735972469975+0300)/").unwrap();
+
+        assert_eq!(date.timestamp_millis(), 1735972469975);
+        assert_eq!(date.offset().local_minus_utc(), 3 * 3600);
+        assert_eq!(date.to_rfc3339(), "2025-01-04T09:34:29.975+03:00");
+    }
+
+    #[test]
+    fn test_parse_date_without_offset() {
+        let date = parse_ms_date("/Date(1735972469975)/").unwrap();
+
+        assert_eq!(date.timestamp_millis(), 1735972469975);
+        assert_eq!(date.offset().local_minus_utc(), 0);
+    }
+
+    #[test]
+    fn test_parse_negative_epoch_and_offset() {
+        let date = parse_ms_date("/Date(-86400000-0530)/").unwrap();
+
+        assert_eq!(date.timestamp_millis(), -86400000);
+        assert_eq!(date.offset().local_minus_utc(), -(5 * 3600 + 30 * 60));
+    }
+
+    #[test]
+    fn test_parse_malformed_date() {
+        for value in ["", "/Date()/", "/Date(abc)/", "/Date(123+03)/", "1735972469975", "/Date(1+0300"] {
+            assert!(matches!(
+                parse_ms_date(value),
+                Err(SpicError::DateParseError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_auth_response_keeps_offset() {
+        let json = r#"{"ExpireDate":"\/Date(1735972469975+0300)\/","IsAuthenticated":true,"IsAuthorized":true,"SessionId":"9960780c-5835-4764-b121-5f7e60a52d2c","UserId":2552,"UserName":"user"}"#.to_string();
+        let auth_response = AuthResponse::from_json(&json).unwrap();
+
+        assert_eq!(auth_response.expire_date.offset().local_minus_utc(), 3 * 3600);
+        assert_eq!(auth_response.expire_date.timestamp_millis(), 1735972469975);
+    }
+}
+
+#[cfg(test)]
+mod unit_group_tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_group_list_from_json() {
+        let json = r#"{
+            "UnitGroups": [
+                { "UnitGroupId": 1, "Name": "Contractors", "Description": null, "CompanyId": 10, "UnitIds": [82697, 82698] },
+                { "Id": 2, "Name": "Empty" }
+            ]
+        }"#
+        .to_string();
+        let groups = SpicUnitGroupList::from_json(&json).unwrap().groups;
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].unit_ids, vec![82697, 82698]);
+        assert_eq!(groups[0].company_id, Some(10));
+        assert_eq!(groups[1].id, 2);
+        assert!(groups[1].unit_ids.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod history_tests {
+    use super::*;
+
+    #[test]
+    fn test_format_ms_date_round_trip() {
+        let date = DateTime::from_timestamp_millis(1735972469975).unwrap();
+        let formatted = format_ms_date(&date);
+
+        assert_eq!(formatted, "/Date(1735972469975)/");
+        assert_eq!(parse_ms_date(&formatted).unwrap(), date);
+    }
+
+    #[test]
+    fn test_history_response_from_json() {
+        let json = r#"{
+            "Messages": [
+                {
+                    "MessageTime": "/Date(1735972469975+0500)/",
+                    "Navigation": {
+                        "AltitudeMeters": 270, "Angle": 90, "HardwareValidation": null,
+                        "Location": { "Latitude": 56.8, "Longitude": 60.6 },
+                        "NavigationSystemType": "Gps", "SatellitesCount": 9, "Speed": 42
+                    }
+                },
+                { "MessageTime": "/Date(1735972479975+0500)/", "Navigation": null }
+            ]
+        }"#
+        .to_string();
+        let messages = HistoryResponse::from_json(&json).unwrap().messages;
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].navigation.as_ref().unwrap().speed, 42);
+        assert!(messages[1].navigation.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_history_paging_stops_when_skip_is_ignored() {
+        let navigation = r#"{"AltitudeMeters":270,"Angle":90,"HardwareValidation":null,"Location":{"Latitude":56.8,"Longitude":60.6},"NavigationSystemType":"Gps","SatellitesCount":9,"Speed":42}"#;
+        let page_size = builder_tests::test_config().history_page_size;
+        let messages = (0..page_size as i64)
+            .map(|i| format!(r#"{{"MessageTime":"/Date({}+0500)/","Navigation":{}}}"#, 1735972469975 + i * 10_000, navigation))
+            .collect::<Vec<_>>();
+        let page = format!(r#"{{"Messages":[{}]}}"#, messages.join(","));
+        // Only one full page is recorded, replay serves it for every later request
+        let (client, _fixtures) =
+            builder_tests::replay_client(&[("post_spic_messagesservice_rest_getmessages.0", &page)]);
+
+        let to = Utc::now();
+        let points = client.track_history(1, to - Duration::hours(1), to).await.unwrap();
+
+        assert_eq!(points.len(), page_size);
+    }
+}
+
+#[cfg(test)]
+mod builder_tests {
+    use super::*;
+    use crate::secret::PasswordSource;
+
+    pub(super) fn test_config() -> SpicConfig {
+        SpicConfig {
+            account: "default".to_string(),
+            base_url: "http://login.scout-gps.ru/spic".to_string(),
+            endpoints: Default::default(),
+            login: "login".to_string(),
+            password: PasswordSource::Plain(Secret::new("password")),
+            timezone: chrono_tz::Asia::Yekaterinburg,
+            culture: "ru-ru".to_string(),
+            ui_culture: "ru-ru".to_string(),
+            subscription_timeout_ms: 15000,
+            subscription_poll_interval_ms: 500,
+            online_data_chunk_size: 100,
+            token_refresh_margin_s: 600,
+            history_window_hours: 24,
+            history_page_size: 1000,
+            connect_timeout_ms: None,
+            logout_on_exit: true,
+            proxy: None,
+            user_agent: None,
+            transport: Default::default(),
+            accounts: Default::default(),
+        }
+    }
+
+    /// A client answering from fixtures instead of SPIC, `fixtures` pairs a
+    /// fixture name like `get_spic_units_rest.0` with the response body. The
+    /// login is added. Fixtures live as long as the returned directory.
+    pub(super) fn replay_client(fixtures: &[(&str, &str)]) -> (SpicClient, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let login = r#"{"ExpireDate":"\/Date(4102444800000+0300)\/","IsAuthenticated":true,"IsAuthorized":true,"SessionId":"***","UserId":2552,"UserName":"user"}"#;
+
+        for (name, body) in std::iter::once(&("post_spic_auth_rest_login.0", login)).chain(fixtures) {
+            let method = name.split('_').next().unwrap_or_default().to_ascii_uppercase();
+            let fixture = json!({ "method": method, "url": "", "status": 200, "body": body });
+            std::fs::write(dir.path().join(format!("{}.json", name)), fixture.to_string()).unwrap();
+        }
+
+        let mut config = test_config();
+        config.base_url = "http://spic.invalid/spic".to_string();
+        // Fixtures are replayed whatever password is sent, none needs to be set up
+        config.password = PasswordSource::Located(crate::secret::PasswordLocation::Env {
+            var: "SC_RDL_TEST_REPLAY_PASSWORD_MISSING".to_string(),
+        });
+
+        let client = SpicClientBuilder::new(config)
+            .transport(Transport::new(TransportMode::Replay, dir.path()))
+            .build()
+            .unwrap();
+
+        (client, dir)
+    }
+
+    #[tokio::test]
+    async fn test_replay_without_network() {
+        let (client, _fixtures) = replay_client(&[("get_spic_units_rest.0", "42")]);
+
+        client.authenticate().await.unwrap();
+        assert_eq!(client.number_of_units().await.unwrap(), 42);
+    }
+
+    #[test]
+    fn test_base_url_override() {
+        let client = SpicClientBuilder::new(test_config())
+            .base_url("http://127.0.0.1:8080/spic/")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            client.endpoints.unit_list_service,
+            "http://127.0.0.1:8080/spic/Units/rest/GetAllUnits"
+        );
+    }
+
+    #[test]
+    fn test_accounts_inherit_from_spic() {
+        let mut config = test_config();
+        config.accounts.insert(
+            "contractor".to_string(),
+            crate::rdl_config::SpicAccountConfig {
+                login: "contractor".to_string(),
+                password: PasswordSource::Plain(Secret::new("secret")),
+                base_url: None,
+            },
+        );
+
+        let clients = config
+            .account_configs()
+            .into_iter()
+            .map(|config| SpicClientBuilder::new(config).build().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(clients.len(), 2);
+        assert_eq!(clients[0].account(), "default");
+        assert_eq!(clients[1].account(), "contractor");
+        assert_eq!(clients[1].config.login, "contractor");
+        assert_eq!(clients[1].endpoints.unit_list_service, clients[0].endpoints.unit_list_service);
+        assert!(clients[1].config.accounts.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_proxy_is_rejected() {
+        let result = SpicClientBuilder::new(test_config())
+            .proxy("not a url")
+            .build();
+
+        assert!(matches!(result, Err(SpicError::NetworkError(_))));
+    }
+
+    #[test]
+    fn test_prebuilt_http_client() {
+        let client = SpicClientBuilder::new(test_config())
+            .user_agent("ignored")
+            .connect_timeout(std::time::Duration::from_secs(1))
+            .http_client(Client::new())
+            .build();
+
+        assert!(client.is_ok());
+    }
+}
+
+#[cfg(test)]
+mod shared_client_tests {
+    use super::builder_tests::test_config;
+    use super::*;
+
+    /// Nothing listens there, any request fails right away.
+    fn offline_client() -> SpicClient {
+        SpicClientBuilder::new(test_config())
+            .base_url("http://127.0.0.1:9")
+            .retry(RetryPolicy::new(1, std::time::Duration::ZERO, std::time::Duration::ZERO, 1.0))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_clones_share_session() {
+        let client = offline_client();
+        let clone = client.clone();
+
+        client.session.set(AuthToken::new(Secret::new("token"), Utc::now() + Duration::hours(1)));
+
+        assert!(clone.refresh_due_in().is_some());
+        assert_eq!(clone.session.generation(), client.session.generation());
+    }
+
+    #[tokio::test]
+    async fn test_relogin_is_skipped_after_concurrent_login() {
+        let client = offline_client();
+        let seen_generation = client.session.generation();
+
+        // Another caller logged in while this one waited for the lock
+        client.session.set(AuthToken::new(Secret::new("token"), Utc::now() + Duration::hours(1)));
+
+        assert!(client.relogin(seen_generation, true).await.is_ok());
+        assert!(client.relogin(client.session.generation(), true).await.is_err());
+    }
+}
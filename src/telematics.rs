@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::retry::Retryable;
+use crate::spic_client::SpicError;
+use crate::wialon_client::WialonError;
+
+#[derive(thiserror::Error, Debug)]
+pub enum TelematicsError {
+    #[error(transparent)]
+    Spic(#[from] SpicError),
+
+    #[error(transparent)]
+    Wialon(#[from] WialonError),
+}
+
+impl TelematicsError {
+    /// True when the provider is down or overloaded rather than the request
+    /// being wrong, so cached data may stand in for the answer.
+    pub fn is_outage(&self) -> bool {
+        match self {
+            TelematicsError::Spic(e) => {
+                e.is_retryable() || matches!(e, SpicError::CircuitOpen { .. } | SpicError::SubscriptionTimeout { .. })
+            }
+            TelematicsError::Wialon(e) => e.is_retryable() || matches!(e, WialonError::CircuitOpen { .. }),
+        }
+    }
+}
+
+/// Vehicle as known to any provider. Whatever does not fit the common
+/// fields is kept in `extras` as the provider sent it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Unit {
+    pub id: i64,
+    pub name: String,
+    /// State number / registration plate
+    pub registration: Option<String>,
+    pub vin: Option<String>,
+    pub model: Option<String>,
+    pub extras: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Last known position of a unit.
+#[derive(Debug, Clone, Serialize)]
+pub struct Position {
+    pub unit_id: i64,
+    pub time: DateTime<Utc>,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// km/h
+    pub speed: Option<f64>,
+    /// Degrees clockwise from north
+    pub course: Option<i32>,
+    /// Meters
+    pub altitude: Option<f64>,
+    pub satellites: Option<i32>,
+    pub is_valid: bool,
+    pub extras: serde_json::Map<String, serde_json::Value>,
+}
+
+/// What the sync and storage layers need from a telematics service.
+// Providers are awaited on the task that owns them, the futures need no `Send` bound
+#[allow(async_fn_in_trait)]
+pub trait TelematicsProvider {
+    /// Short provider name stored with the data, e.g. `spic`.
+    fn provider(&self) -> &'static str;
+
+    /// Account name from the config, stored with the data.
+    fn account(&self) -> &str;
+
+    /// True while the provider is considered down and cached data should be
+    /// served instead.
+    fn is_unavailable(&self) -> bool;
+
+    async fn authenticate(&self) -> Result<(), TelematicsError>;
+
+    async fn logout(&self) -> Result<(), TelematicsError>;
+
+    async fn units(&self) -> Result<Vec<Unit>, TelematicsError>;
+
+    /// Current positions of the units, units without a known position are
+    /// left out.
+    async fn positions(&self, unit_ids: &[i64]) -> Result<HashMap<i64, Position>, TelematicsError>;
+}
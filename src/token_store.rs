@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use chrono::{DateTime, Utc};
+use keyring::Entry;
+use serde::{Deserialize, Serialize};
+
+use crate::rdl_config::TokenStoreConfig;
+use crate::secret::Secret;
+
+const NONCE_LENGTH: usize = 12;
+
+/// Session cached between runs, so a restart does not need a new login.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredToken {
+    pub token: Secret,
+    pub expiration: DateTime<Utc>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TokenStoreError {
+    #[error("Keyring error: {0}")]
+    Keyring(#[from] keyring::Error),
+
+    #[error("Token file error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Stored token is malformed: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Invalid token store key: {0}")]
+    InvalidKey(String),
+
+    #[error("Stored token for {login} could not be decrypted")]
+    Decrypt { login: String },
+}
+
+/// Where sessions are cached between runs. Entries are kept per SPIC login,
+/// so several accounts do not overwrite each other.
+pub trait TokenStore: Debug + Send + Sync {
+    fn load(&self, login: &str) -> Result<Option<StoredToken>, TokenStoreError>;
+
+    fn store(&self, login: &str, token: &StoredToken) -> Result<(), TokenStoreError>;
+
+    fn clear(&self, login: &str) -> Result<(), TokenStoreError>;
+}
+
+pub fn from_config(config: &TokenStoreConfig) -> Result<Arc<dyn TokenStore>, TokenStoreError> {
+    Ok(match config {
+        TokenStoreConfig::Keyring { service } => Arc::new(KeyringTokenStore::new(service)),
+        TokenStoreConfig::EncryptedFile {
+            path,
+            key_path,
+            key_env,
+        } => {
+            let key = match key_env.as_deref().and_then(|name| std::env::var(name).ok()) {
+                Some(hex_key) => parse_key(&hex_key)?,
+                None => load_or_create_key(Path::new(key_path))?,
+            };
+            Arc::new(EncryptedFileTokenStore::new(path, key))
+        }
+        TokenStoreConfig::Memory => Arc::new(MemoryTokenStore::default()),
+    })
+}
+
+/// OS credential store: Windows Credential Manager or the Linux kernel keyring.
+/// Tokens are kept under `<login>/token`, so they do not overwrite a SPIC
+/// password stored in the same service under the bare login.
+#[derive(Debug)]
+pub struct KeyringTokenStore {
+    service: String,
+}
+
+impl KeyringTokenStore {
+    pub fn new(service: &str) -> Self {
+        KeyringTokenStore {
+            service: service.to_string(),
+        }
+    }
+
+    fn entry(&self, login: &str) -> Result<Entry, TokenStoreError> {
+        Ok(Entry::new(&self.service, &token_entry_user(login))?)
+    }
+}
+
+impl TokenStore for KeyringTokenStore {
+    fn load(&self, login: &str) -> Result<Option<StoredToken>, TokenStoreError> {
+        match self.entry(login)?.get_password() {
+            Ok(json) => Ok(Some(serde_json::from_str(&json)?)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn store(&self, login: &str, token: &StoredToken) -> Result<(), TokenStoreError> {
+        self.entry(login)?.set_password(&serde_json::to_string(token)?)?;
+        Ok(())
+    }
+
+    fn clear(&self, login: &str) -> Result<(), TokenStoreError> {
+        match self.entry(login)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+fn token_entry_user(login: &str) -> String {
+    format!("{}/token", login)
+}
+
+/// JSON file mapping each login to its AES-256-GCM encrypted token, for
+/// servers without a usable keyring.
+pub struct EncryptedFileTokenStore {
+    path: PathBuf,
+    cipher: Aes256Gcm,
+    // Serializes read-modify-write of the file between clients of this process
+    lock: Mutex<()>,
+}
+
+impl Debug for EncryptedFileTokenStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptedFileTokenStore")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EncryptedFileTokenStore {
+    pub fn new(path: impl Into<PathBuf>, key: [u8; 32]) -> Self {
+        EncryptedFileTokenStore {
+            path: path.into(),
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn read_entries(&self) -> Result<HashMap<String, String>, TokenStoreError> {
+        match fs::read_to_string(&self.path) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_entries(&self, entries: &HashMap<String, String>) -> Result<(), TokenStoreError> {
+        // Replaced through a temporary file, a crash never leaves half a file
+        let tmp_path = self.path.with_extension("tmp");
+        write_private(&tmp_path, serde_json::to_string(entries)?.as_bytes())?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+
+    fn encrypt(&self, token: &StoredToken) -> Result<String, TokenStoreError> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let plaintext = serde_json::to_vec(token)?;
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_slice())
+            .expect("AES-GCM encryption of a short buffer does not fail");
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(hex::encode(sealed))
+    }
+
+    fn decrypt(&self, login: &str, sealed: &str) -> Result<StoredToken, TokenStoreError> {
+        let decrypt_error = || TokenStoreError::Decrypt {
+            login: login.to_string(),
+        };
+
+        let sealed = hex::decode(sealed).map_err(|_| decrypt_error())?;
+        if sealed.len() < NONCE_LENGTH {
+            return Err(decrypt_error());
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| decrypt_error())?;
+
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+}
+
+impl TokenStore for EncryptedFileTokenStore {
+    fn load(&self, login: &str) -> Result<Option<StoredToken>, TokenStoreError> {
+        let _lock = self.lock.lock().unwrap();
+
+        self.read_entries()?
+            .get(login)
+            .map(|sealed| self.decrypt(login, sealed))
+            .transpose()
+    }
+
+    fn store(&self, login: &str, token: &StoredToken) -> Result<(), TokenStoreError> {
+        let _lock = self.lock.lock().unwrap();
+
+        let mut entries = self.read_entries()?;
+        entries.insert(login.to_string(), self.encrypt(token)?);
+        self.write_entries(&entries)
+    }
+
+    fn clear(&self, login: &str) -> Result<(), TokenStoreError> {
+        let _lock = self.lock.lock().unwrap();
+
+        let mut entries = self.read_entries()?;
+        if entries.remove(login).is_some() {
+            self.write_entries(&entries)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps sessions for the lifetime of the process only.
+#[derive(Debug, Default)]
+pub struct MemoryTokenStore {
+    tokens: Mutex<HashMap<String, StoredToken>>,
+}
+
+impl TokenStore for MemoryTokenStore {
+    fn load(&self, login: &str) -> Result<Option<StoredToken>, TokenStoreError> {
+        Ok(self.tokens.lock().unwrap().get(login).cloned())
+    }
+
+    fn store(&self, login: &str, token: &StoredToken) -> Result<(), TokenStoreError> {
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(login.to_string(), token.clone());
+        Ok(())
+    }
+
+    fn clear(&self, login: &str) -> Result<(), TokenStoreError> {
+        self.tokens.lock().unwrap().remove(login);
+        Ok(())
+    }
+}
+
+/// Key as 64 hex characters.
+fn parse_key(hex_key: &str) -> Result<[u8; 32], TokenStoreError> {
+    let bytes = hex::decode(hex_key.trim()).map_err(|e| TokenStoreError::InvalidKey(e.to_string()))?;
+
+    bytes
+        .try_into()
+        .map_err(|bytes: Vec<u8>| TokenStoreError::InvalidKey(format!("expected 32 bytes, got {}", bytes.len())))
+}
+
+/// Reads the key file, a random key is generated on first use.
+fn load_or_create_key(path: &Path) -> Result<[u8; 32], TokenStoreError> {
+    match fs::read_to_string(path) {
+        Ok(hex_key) => parse_key(&hex_key),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let key = Aes256Gcm::generate_key(&mut OsRng);
+            write_private(path, hex::encode(key).as_bytes())?;
+            println!("Generated token store key at {}", path.display());
+            Ok(key.into())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Writes a file only the current user can read.
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)?.write_all(contents)
+}
+
+#[cfg(test)]
+mod token_store_tests {
+    use super::*;
+
+    fn token(value: &str) -> StoredToken {
+        StoredToken {
+            token: Secret::new(value),
+            expiration: DateTime::from_timestamp(1735972469, 0).unwrap(),
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sc-rdl-{}-{}.json", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_memory_store_keeps_logins_apart() {
+        let store = MemoryTokenStore::default();
+
+        store.store("first", &token("a")).unwrap();
+        store.store("second", &token("b")).unwrap();
+        store.clear("first").unwrap();
+
+        assert_eq!(store.load("first").unwrap(), None);
+        assert_eq!(store.load("second").unwrap(), Some(token("b")));
+    }
+
+    #[test]
+    fn test_encrypted_file_round_trip() {
+        let path = temp_path("round-trip");
+        let store = EncryptedFileTokenStore::new(&path, [7; 32]);
+
+        store.store("first", &token("a")).unwrap();
+        store.store("second", &token("b")).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains("\"a\""));
+
+        let reopened = EncryptedFileTokenStore::new(&path, [7; 32]);
+        assert_eq!(reopened.load("first").unwrap(), Some(token("a")));
+        assert_eq!(reopened.load("second").unwrap(), Some(token("b")));
+        assert_eq!(reopened.load("third").unwrap(), None);
+
+        reopened.clear("first").unwrap();
+        assert_eq!(store.load("first").unwrap(), None);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_file_wrong_key() {
+        let path = temp_path("wrong-key");
+        EncryptedFileTokenStore::new(&path, [7; 32])
+            .store("first", &token("a"))
+            .unwrap();
+
+        let result = EncryptedFileTokenStore::new(&path, [8; 32]).load("first");
+
+        assert!(matches!(result, Err(TokenStoreError::Decrypt { .. })));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(parse_key(&"01".repeat(32)).unwrap(), [1; 32]);
+        assert!(parse_key("0102").is_err());
+        assert!(parse_key("not hex").is_err());
+    }
+}
@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use reqwest::{header, Client, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::rdl_config::TransportConfig;
+
+/// Request body fields that are never written to a fixture.
+const REDACTED_REQUEST_FIELDS: [&str; 1] = ["Password"];
+/// Response body fields that are replaced before writing a fixture. Only
+/// string values are replaced: the login's `SessionId` is the session token,
+/// while the `SessionId` object of Subscribe is a subscription id the
+/// following requests need.
+const REDACTED_RESPONSE_FIELDS: [&str; 1] = ["SessionId"];
+const REDACTED: &str = "***";
+
+#[derive(thiserror::Error, Debug)]
+pub enum TransportError {
+    #[error("Network error: {0}")]
+    Network(#[from] reqwest::Error),
+
+    #[error("No fixture {path} to replay")]
+    MissingFixture { path: PathBuf },
+
+    #[error("Unable to read fixture {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Fixture {path} is malformed: {source}")]
+    Format {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportMode {
+    Live,
+    Record,
+    Replay,
+}
+
+/// One request/response pair as stored in the fixture directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Fixture {
+    method: String,
+    url: String,
+    #[serde(default)]
+    request_body: Option<String>,
+    status: u16,
+    #[serde(default)]
+    content_type: Option<String>,
+    body: String,
+}
+
+/// Sends SPIC requests over the network, optionally saving every exchange
+/// as a fixture, or answers them from saved fixtures without any network.
+///
+/// The n-th request to the same method and path is stored as
+/// `<method>_<path>.<n>.json`. On replay a request beyond the recorded ones
+/// gets the last recorded answer, so polling loops run until they are done.
+#[derive(Debug)]
+pub struct Transport {
+    mode: TransportMode,
+    dir: PathBuf,
+    calls: Mutex<HashMap<String, usize>>,
+}
+
+impl Transport {
+    pub fn live() -> Self {
+        Transport::new(TransportMode::Live, PathBuf::new())
+    }
+
+    pub fn new(mode: TransportMode, dir: impl Into<PathBuf>) -> Self {
+        Transport {
+            mode,
+            dir: dir.into(),
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Fixtures of each account are kept in a subdirectory named after it.
+    pub fn from_config(config: &TransportConfig, account: &str) -> Self {
+        match config {
+            TransportConfig::Live => Transport::live(),
+            TransportConfig::Record { fixtures } => Transport::new(TransportMode::Record, Path::new(fixtures).join(account)),
+            TransportConfig::Replay { fixtures } => Transport::new(TransportMode::Replay, Path::new(fixtures).join(account)),
+        }
+    }
+
+    pub fn mode(&self) -> TransportMode {
+        self.mode
+    }
+
+    pub async fn send(&self, client: &Client, request: RequestBuilder) -> Result<Response, TransportError> {
+        if self.mode == TransportMode::Live {
+            return Ok(request.send().await?);
+        }
+
+        let request = request.build()?;
+        let path = self.next_fixture_path(request.method().as_str(), request.url().path());
+
+        match self.mode {
+            TransportMode::Replay => self.replay(&path).map(into_response),
+            _ => {
+                let method = request.method().to_string();
+                let url = request.url().to_string();
+                let request_body = request
+                    .body()
+                    .and_then(|body| body.as_bytes())
+                    .map(|body| redact(&String::from_utf8_lossy(body), &REDACTED_REQUEST_FIELDS));
+
+                let response = client.execute(request).await?;
+                let fixture = Fixture {
+                    method,
+                    url,
+                    request_body,
+                    status: response.status().as_u16(),
+                    content_type: response
+                        .headers()
+                        .get(header::CONTENT_TYPE)
+                        .and_then(|value| value.to_str().ok())
+                        .map(str::to_string),
+                    body: response.text().await?,
+                };
+
+                if let Err(e) = write_fixture(&path, &fixture) {
+                    println!("Failed to record fixture {}: {}", path.display(), e);
+                }
+
+                Ok(into_response(fixture))
+            }
+        }
+    }
+
+    fn next_fixture_path(&self, method: &str, url_path: &str) -> PathBuf {
+        let name = fixture_name(method, url_path);
+        let mut calls = self.calls.lock().unwrap();
+        let call = calls.entry(name.clone()).or_insert(0);
+        let path = self.dir.join(format!("{}.{}.json", name, call));
+        *call += 1;
+        path
+    }
+
+    /// The fixture at `path` or, past the end of the recording, the last
+    /// one recorded for the same request.
+    fn replay(&self, path: &Path) -> Result<Fixture, TransportError> {
+        let mut candidate = path.to_path_buf();
+
+        loop {
+            match fs::read_to_string(&candidate) {
+                Ok(json) => {
+                    return serde_json::from_str(&json).map_err(|source| TransportError::Format {
+                        path: candidate,
+                        source,
+                    })
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => match previous_fixture(&candidate) {
+                    Some(previous) => candidate = previous,
+                    None => {
+                        return Err(TransportError::MissingFixture {
+                            path: path.to_path_buf(),
+                        })
+                    }
+                },
+                Err(source) => return Err(TransportError::Io { path: candidate, source }),
+            }
+        }
+    }
+}
+
+/// `POST /auth/rest/login` becomes `post_auth_rest_login`.
+fn fixture_name(method: &str, url_path: &str) -> String {
+    let path = url_path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            segment
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("_");
+
+    format!("{}_{}", method.to_ascii_lowercase(), path)
+}
+
+/// `name.3.json` gives `name.2.json`, `name.0.json` gives nothing.
+fn previous_fixture(path: &Path) -> Option<PathBuf> {
+    let stem = path.file_stem()?.to_str()?;
+    let (name, index) = stem.rsplit_once('.')?;
+    let index = index.parse::<usize>().ok()?.checked_sub(1)?;
+
+    Some(path.with_file_name(format!("{}.{}.json", name, index)))
+}
+
+/// Replaces the given top level string fields of a JSON object, other
+/// values and bodies are kept as they are.
+fn redact(body: &str, fields: &[&str]) -> String {
+    match serde_json::from_str::<serde_json::Value>(body) {
+        Ok(serde_json::Value::Object(mut object)) => {
+            for field in fields {
+                if let Some(value @ serde_json::Value::String(_)) = object.get_mut(*field) {
+                    *value = serde_json::Value::String(REDACTED.to_string());
+                }
+            }
+            serde_json::Value::Object(object).to_string()
+        }
+        _ => body.to_string(),
+    }
+}
+
+fn write_fixture(path: &Path, fixture: &Fixture) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut stored = fixture.clone();
+    stored.body = redact(&fixture.body, &REDACTED_RESPONSE_FIELDS);
+
+    fs::write(path, serde_json::to_string_pretty(&stored)?)
+}
+
+fn into_response(fixture: Fixture) -> Response {
+    let mut response = http::Response::builder().status(fixture.status);
+    if let Some(content_type) = &fixture.content_type {
+        response = response.header(header::CONTENT_TYPE, content_type);
+    }
+
+    response
+        .body(fixture.body)
+        .map(Response::from)
+        .unwrap_or_else(|_| Response::from(http::Response::new(String::new())))
+}
+
+#[cfg(test)]
+mod transport_tests {
+    use super::*;
+
+    #[test]
+    fn test_fixture_name() {
+        assert_eq!(fixture_name("POST", "/spic/auth/rest/Login"), "post_spic_auth_rest_login");
+        assert_eq!(fixture_name("GET", "/spic/Units/rest/"), "get_spic_units_rest");
+        assert_eq!(previous_fixture(Path::new("a/get_x.2.json")), Some(PathBuf::from("a/get_x.1.json")));
+        assert_eq!(previous_fixture(Path::new("a/get_x.0.json")), None);
+    }
+
+    #[test]
+    fn test_redact() {
+        let body = redact(r#"{"Login":"user","Password":"hunter2"}"#, &REDACTED_REQUEST_FIELDS);
+
+        assert!(!body.contains("hunter2"));
+        assert!(body.contains("user"));
+        assert_eq!(redact("42", &REDACTED_REQUEST_FIELDS), "42");
+
+        let login = redact(r#"{"SessionId":"secret-token"}"#, &REDACTED_RESPONSE_FIELDS);
+        assert!(!login.contains("secret-token"));
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_survives_record_and_replay() {
+        let body = r#"{"SessionId":{"Id":"0f8fad5b-d9cb-469f-a165-70867728950e"},"State":{"ErrorCodes":[],"Status":{"Value":"Ok"}}}"#;
+        let app = axum::Router::new().route(
+            "/spic/OnlineDataService/rest/Subscribe",
+            axum::routing::post(move || async move { ([(header::CONTENT_TYPE, "application/json")], body) }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/spic/OnlineDataService/rest/Subscribe", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let fixtures = tempfile::tempdir().unwrap();
+        let dir = fixtures.path();
+        let client = Client::new();
+
+        let recorded = Transport::new(TransportMode::Record, dir)
+            .send(&client, client.post(&url).body("[1]"))
+            .await
+            .unwrap();
+        assert_eq!(recorded.text().await.unwrap(), body);
+        server.abort();
+
+        let replayed = Transport::new(TransportMode::Replay, dir)
+            .send(&client, client.post(&url).body("[1]"))
+            .await
+            .unwrap();
+        let replayed: serde_json::Value = replayed.json().await.unwrap();
+
+        assert_eq!(replayed["SessionId"]["Id"], "0f8fad5b-d9cb-469f-a165-70867728950e");
+    }
+
+    #[tokio::test]
+    async fn test_replay_serves_recorded_responses_in_order() {
+        let fixtures = tempfile::tempdir().unwrap();
+        let dir = fixtures.path();
+        let client = Client::new();
+        let url = "http://spic.invalid/spic/OnlineDataService/rest/GetOnlineData";
+
+        for (index, state) in ["Busy", "Ready"].iter().enumerate() {
+            let fixture = Fixture {
+                method: "POST".to_string(),
+                url: url.to_string(),
+                request_body: None,
+                status: 200,
+                content_type: Some("application/json".to_string()),
+                body: format!(r#"{{"State":"{}"}}"#, state),
+            };
+            write_fixture(&dir.join(format!("post_spic_onlinedataservice_rest_getonlinedata.{}.json", index)), &fixture).unwrap();
+        }
+
+        let transport = Transport::new(TransportMode::Replay, dir);
+        let mut bodies = Vec::new();
+        for _ in 0..3 {
+            let response = transport.send(&client, client.post(url)).await.unwrap();
+            assert_eq!(response.status(), reqwest::StatusCode::OK);
+            bodies.push(response.text().await.unwrap());
+        }
+
+        assert_eq!(bodies, [r#"{"State":"Busy"}"#, r#"{"State":"Ready"}"#, r#"{"State":"Ready"}"#]);
+
+        let missing = transport.send(&client, client.get("http://spic.invalid/spic/Units/rest/")).await;
+        assert!(matches!(missing, Err(TransportError::MissingFixture { .. })));
+    }
+}
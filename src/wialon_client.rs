@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::DateTime;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::conf as conf;
+use crate::rdl_config::{CircuitBreakerConfig, WialonConfig};
+use crate::retry::{with_retry, RetryPolicy, Retryable};
+use crate::secret::{Secret, SecretError};
+use crate::session::{Session, SessionError};
+use crate::telematics::{Position, TelematicsError, TelematicsProvider, Unit};
+
+/// Base properties (1), last message and position (1024) and profile
+/// fields (8388608) of `core/search_items`.
+const UNIT_FLAGS: u64 = 1 | 1024 | 8388608;
+
+const ERROR_INVALID_SESSION: i32 = 1;
+const ERROR_REQUEST_FAILED: i32 = 5;
+const ERROR_UNKNOWN: i32 = 6;
+const ERROR_CONCURRENT_REQUEST: i32 = 1003;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WialonError {
+    #[error("Network error: {0}")]
+    NetworkError(#[from] reqwest::Error),
+
+    #[error("JSON parsing error: {source} \n in {svc}")]
+    JsonError {
+        svc: &'static str,
+        data: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("HTTP {status} from Wialon {svc}: {body}")]
+    HttpStatus {
+        svc: &'static str,
+        status: reqwest::StatusCode,
+        body: String,
+    },
+
+    #[error("Wialon error {code} in {svc}: {reason}")]
+    Api {
+        svc: &'static str,
+        code: i32,
+        reason: String,
+    },
+
+    #[error("Unable to get the Wialon token: {0}")]
+    Token(#[from] SecretError),
+
+    #[error("Wialon is considered down, {svc} not sent, next probe in {retry_in_ms} ms")]
+    CircuitOpen { svc: &'static str, retry_in_ms: u128 },
+}
+
+impl WialonError {
+    fn is_invalid_session(&self) -> bool {
+        matches!(self, WialonError::Api { code: ERROR_INVALID_SESSION, .. })
+    }
+}
+
+impl Retryable for WialonError {
+    fn is_retryable(&self) -> bool {
+        match self {
+            WialonError::NetworkError(e) => match e.status() {
+                Some(status) => status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+                None => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+            },
+            WialonError::HttpStatus { status, .. } => {
+                status.is_server_error() || *status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            }
+            WialonError::Api { code, .. } => {
+                matches!(*code, ERROR_REQUEST_FAILED | ERROR_UNKNOWN | ERROR_CONCURRENT_REQUEST)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl SessionError for WialonError {
+    fn is_rejected(&self) -> bool {
+        self.is_invalid_session()
+    }
+
+    fn circuit_open(svc: &'static str, retry_in: Duration) -> Self {
+        WialonError::CircuitOpen {
+            svc,
+            retry_in_ms: retry_in.as_millis(),
+        }
+    }
+}
+
+/// Handle to a Wialon account using the Remote API (`/wialon/ajax.html`).
+/// Clones are cheap and share the session.
+#[derive(Debug, Clone)]
+pub struct WialonClient {
+    client: Client,
+    account: String,
+    ajax_url: String,
+    token: Secret,
+    /// Session id (`eid`) of the current login
+    session: Arc<Session<Secret>>,
+    retry: RetryPolicy,
+}
+
+#[derive(Debug, Deserialize)]
+struct LoginResponse {
+    eid: Secret,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResponse {
+    #[serde(default)]
+    items: Vec<WialonUnit>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct WialonUnit {
+    id: i64,
+    nm: String,
+    #[serde(default)]
+    pos: Option<WialonPosition>,
+    /// Profile fields by id, e.g. `{"1": {"n": "vin", "v": "..."}}`
+    #[serde(default)]
+    pflds: HashMap<String, ProfileField>,
+    #[serde(flatten)]
+    extras: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ProfileField {
+    n: String,
+    v: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct WialonPosition {
+    /// Unix time of the message
+    t: i64,
+    y: f64,
+    x: f64,
+    #[serde(default)]
+    z: Option<f64>,
+    #[serde(default)]
+    s: Option<f64>,
+    #[serde(default)]
+    c: Option<i32>,
+    #[serde(default)]
+    sc: Option<i32>,
+}
+
+impl WialonUnit {
+    fn profile_field(&self, name: &str) -> Option<String> {
+        self.pflds
+            .values()
+            .find(|field| field.n == name && !field.v.is_empty())
+            .map(|field| field.v.clone())
+    }
+
+    fn to_position(&self) -> Option<Position> {
+        let pos = self.pos.as_ref()?;
+
+        Some(Position {
+            unit_id: self.id,
+            time: DateTime::from_timestamp(pos.t, 0)?,
+            latitude: pos.y,
+            longitude: pos.x,
+            speed: pos.s,
+            course: pos.c,
+            altitude: pos.z,
+            satellites: pos.sc,
+            is_valid: pos.sc.is_none_or(|satellites| satellites > 0),
+            extras: serde_json::Map::new(),
+        })
+    }
+}
+
+impl From<WialonUnit> for Unit {
+    fn from(unit: WialonUnit) -> Self {
+        Unit {
+            id: unit.id,
+            registration: unit.profile_field("registration_plate"),
+            vin: unit.profile_field("vin"),
+            model: unit.profile_field("model"),
+            name: unit.nm,
+            extras: unit.extras,
+        }
+    }
+}
+
+/// Wialon answers failures with HTTP 200 and `{"error": code}`.
+fn check_api_error(svc: &'static str, value: &serde_json::Value) -> Result<(), WialonError> {
+    match value.get("error").and_then(|code| code.as_i64()) {
+        Some(0) | None => Ok(()),
+        Some(code) => Err(WialonError::Api {
+            svc,
+            code: code as i32,
+            reason: value
+                .get("reason")
+                .and_then(|reason| reason.as_str())
+                .unwrap_or_default()
+                .to_string(),
+        }),
+    }
+}
+
+fn parse_response<T: for<'de> Deserialize<'de>>(svc: &'static str, body: &str) -> Result<T, WialonError> {
+    let json_error = |source| WialonError::JsonError {
+        svc,
+        data: body.to_string(),
+        source,
+    };
+
+    let value: serde_json::Value = serde_json::from_str(body).map_err(json_error)?;
+    check_api_error(svc, &value)?;
+
+    serde_json::from_value(value).map_err(json_error)
+}
+
+impl WialonClient {
+    pub fn new(
+        account: &str,
+        config: &WialonConfig,
+        retry: RetryPolicy,
+        circuit_breaker: &CircuitBreakerConfig,
+        client: Client,
+    ) -> Result<Self, WialonError> {
+        Ok(WialonClient {
+            client,
+            account: account.to_string(),
+            ajax_url: format!("{}/wialon/ajax.html", config.base_url.trim_end_matches('/')),
+            token: config.token.resolve(account)?,
+            session: Arc::new(Session::new(circuit_breaker)),
+            retry,
+        })
+    }
+
+    pub fn account(&self) -> &str {
+        &self.account
+    }
+
+    async fn request<T: for<'de> Deserialize<'de>>(
+        &self,
+        svc: &'static str,
+        params: &serde_json::Value,
+        sid: Option<&Secret>,
+    ) -> Result<T, WialonError> {
+        let params = params.to_string();
+        let mut form = vec![("svc", svc), ("params", params.as_str())];
+        if let Some(sid) = sid {
+            form.push(("sid", sid.expose()));
+        }
+
+        let response = self.client.post(&self.ajax_url).form(&form).send().await?;
+
+        match response.status() {
+            reqwest::StatusCode::OK => parse_response(svc, &response.text().await?),
+            status => Err(WialonError::HttpStatus {
+                svc,
+                status,
+                body: response.text().await.unwrap_or_default(),
+            }),
+        }
+    }
+
+    /// Callers hold the login lock of the session.
+    async fn login(&self) -> Result<(), WialonError> {
+        let params = json!({ "token": self.token.expose() });
+
+        let response: LoginResponse = with_retry(&self.retry, "WialonClient::login", || {
+            self.request("token/login", &params, None)
+        })
+        .await?;
+
+        self.session.set(response.eid);
+        println!("Wialon authentication successful for account {}", self.account);
+
+        Ok(())
+    }
+
+    /// Runs a request with the session id, see [`Session::authorized`].
+    async fn authorized<T, F, Fut>(&self, svc: &'static str, mut request: F) -> Result<T, WialonError>
+    where
+        F: FnMut(Option<Secret>) -> Fut,
+        Fut: std::future::Future<Output = Result<T, WialonError>>,
+    {
+        self.session
+            .authorized(
+                &self.retry,
+                svc,
+                || request(self.session.token()),
+                |generation| self.session.relogin(generation, || self.login()),
+            )
+            .await
+    }
+
+    async fn search_units(&self) -> Result<Vec<WialonUnit>, WialonError> {
+        let params = json!({
+            "spec": {
+                "itemsType": "avl_unit",
+                "propName": "sys_name",
+                "propValueMask": "*",
+                "sortType": "sys_name"
+            },
+            "force": 1,
+            "flags": UNIT_FLAGS,
+            "from": 0,
+            "to": 0
+        });
+
+        let response: SearchResponse = self
+            .authorized("core/search_items", |sid| {
+                let params = &params;
+                async move { self.request("core/search_items", params, sid.as_ref()).await }
+            })
+            .await?;
+
+        Ok(response.items)
+    }
+}
+
+impl TelematicsProvider for WialonClient {
+    fn provider(&self) -> &'static str {
+        "wialon"
+    }
+
+    fn account(&self) -> &str {
+        &self.account
+    }
+
+    fn is_unavailable(&self) -> bool {
+        self.session.is_unavailable()
+    }
+
+    async fn authenticate(&self) -> Result<(), TelematicsError> {
+        let _login = self.session.lock_login().await;
+
+        Ok(self.login().await?)
+    }
+
+    async fn logout(&self) -> Result<(), TelematicsError> {
+        let sid = self.session.take();
+
+        if let Some(sid) = sid {
+            let _: serde_json::Value = self.request("core/logout", &json!({}), Some(&sid)).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn units(&self) -> Result<Vec<Unit>, TelematicsError> {
+        Ok(self.search_units().await?.into_iter().map(Unit::from).collect())
+    }
+
+    async fn positions(&self, unit_ids: &[i64]) -> Result<HashMap<i64, Position>, TelematicsError> {
+        Ok(self
+            .search_units()
+            .await?
+            .iter()
+            .filter(|unit| unit_ids.contains(&unit.id))
+            .filter_map(|unit| unit.to_position().map(|position| (unit.id, position)))
+            .collect())
+    }
+}
+
+/// One client per `[wialon.<account>]` section of the global config.
+pub fn init_wialon_clients() -> Result<Vec<WialonClient>, WialonError> {
+    let request_timeout = conf!(server).request_timeout.max(0) as u64;
+    let client = Client::builder()
+        .user_agent(concat!("sc-rdl-rust/", env!("CARGO_PKG_VERSION")))
+        .timeout(std::time::Duration::from_millis(request_timeout))
+        .build()?;
+
+    conf!(wialon)
+        .iter()
+        .map(|(account, config)| {
+            WialonClient::new(
+                account,
+                config,
+                RetryPolicy::from_config(&conf!(retry)),
+                &conf!(circuit_breaker),
+                client.clone(),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod wialon_tests {
+    use super::*;
+
+    #[test]
+    fn test_search_response_to_units_and_positions() {
+        let body = r#"{
+            "searchSpec": {},
+            "totalItemsCount": 2,
+            "items": [
+                {
+                    "nm": "Truck 1", "cls": 2, "id": 734455, "mu": 0,
+                    "pos": { "t": 1735972469, "f": 1, "lc": 0, "y": 56.8, "x": 60.6, "c": 90, "z": 270, "s": 42, "sc": 9 },
+                    "pflds": {
+                        "1": { "id": 1, "n": "registration_plate", "v": "A123BC" },
+                        "2": { "id": 2, "n": "vin", "v": "" }
+                    }
+                },
+                { "nm": "Trailer", "cls": 2, "id": 734456, "mu": 0, "pos": null }
+            ]
+        }"#;
+
+        let items = parse_response::<SearchResponse>("core/search_items", body).unwrap().items;
+        let position = items[0].to_position().unwrap();
+
+        assert_eq!(position.unit_id, 734455);
+        assert_eq!(position.speed, Some(42.0));
+        assert_eq!(position.time.timestamp(), 1735972469);
+        assert!(items[1].to_position().is_none());
+
+        let unit = Unit::from(items.into_iter().next().unwrap());
+        assert_eq!(unit.name, "Truck 1");
+        assert_eq!(unit.registration.as_deref(), Some("A123BC"));
+        assert_eq!(unit.vin, None);
+        assert_eq!(unit.extras.get("cls"), Some(&json!(2)));
+    }
+
+    #[test]
+    fn test_api_error() {
+        let result = parse_response::<SearchResponse>("core/search_items", r#"{"error": 1}"#);
+
+        assert!(result.as_ref().is_err_and(|e| e.is_invalid_session()));
+        assert!(!result.unwrap_err().is_retryable());
+
+        let busy = parse_response::<LoginResponse>("token/login", r#"{"error": 1003, "reason": "busy"}"#);
+        assert!(busy.is_err_and(|e| e.is_retryable()));
+    }
+
+    #[test]
+    fn test_login_response() {
+        let response = parse_response::<LoginResponse>("token/login", r#"{"eid": "abc", "user": {"nm": "user"}}"#).unwrap();
+
+        assert_eq!(response.eid.expose(), "abc");
+    }
+}
@@ -0,0 +1,104 @@
+//! Runs `SpicClient` against the mock SPIC server on a free port.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, Command, Stdio};
+
+use config::{Config, File, FileFormat};
+use sc_rdl_rust::rdl_config::SpicConfig;
+use sc_rdl_rust::spic_client::SpicClientBuilder;
+
+const MOCK_CONFIG: &str = r#"
+address = "127.0.0.1"
+port = 0
+subscription_delay_ms = 200
+busy_probability = 0.0
+
+[fleet]
+units = 20
+offline_share = 0.0
+units_per_group = 5
+"#;
+
+/// Stops the mock when the test ends, also on a failed assert.
+struct Mock(Child);
+
+impl Drop for Mock {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Starts the mock and returns it with the `base_url` it listens on.
+fn start_mock(dir: &tempfile::TempDir) -> (Mock, String) {
+    let config_path = dir.path().join("mock_spic.toml");
+    std::fs::File::create(&config_path)
+        .and_then(|mut file| file.write_all(MOCK_CONFIG.as_bytes()))
+        .unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mock_spic"))
+        .arg(&config_path)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let mock = Mock(child);
+
+    // The mock keeps logging logins and subscriptions, stdout is drained until it exits
+    let (listening, base_url) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            if let Some((_, url)) = line.split_once("listening on ") {
+                let _ = listening.send(url.to_string());
+            }
+        }
+    });
+
+    let base_url = base_url
+        .recv_timeout(std::time::Duration::from_secs(30))
+        .expect("the mock did not start");
+
+    (mock, base_url)
+}
+
+fn spic_config(base_url: &str) -> SpicConfig {
+    let toml = format!(
+        r#"
+        base_url = "{}"
+        login = "mock"
+        password = "mock"
+        subscription_timeout_ms = 5000
+        subscription_poll_interval_ms = 100
+        "#,
+        base_url
+    );
+
+    Config::builder()
+        .add_source(File::from_str(&toml, FileFormat::Toml))
+        .build()
+        .unwrap()
+        .try_deserialize()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_client_against_mock() {
+    let dir = tempfile::tempdir().unwrap();
+    let (_mock, base_url) = start_mock(&dir);
+    let client = SpicClientBuilder::new(spic_config(&base_url)).build().unwrap();
+
+    client.authenticate().await.unwrap();
+
+    assert_eq!(client.number_of_units().await.unwrap(), 20);
+
+    let unit_ids = client.unit_list().await.unwrap().iter().map(|unit| unit.id).collect::<Vec<i32>>();
+    assert_eq!(unit_ids.len(), 20);
+
+    assert_eq!(client.unit_groups().await.unwrap().len(), 4);
+
+    let batch = client.get_online_data_many(&unit_ids).await.unwrap();
+    assert_eq!(batch.data.len(), 20);
+    assert!(batch.missing.is_empty());
+
+    client.logout().await.unwrap();
+}